@@ -1,10 +1,13 @@
-use super::config::read_config;
+use super::config::{default_resources, read_config_if_exists};
+use super::rest::filter::{where_parser, Cond};
+use super::rest::rest::Resolve;
+pub use super::rest::output::OutputFormat;
 use clap::{
     arg, command, ArgMatches, Args, Command, FromArgMatches as _, Parser, Subcommand as _,
     ValueEnum,
 };
 use serde_json::Value;
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 
 #[derive(Args)]
 pub struct Opts {
@@ -34,12 +37,34 @@ pub struct Opts {
     #[arg(
         short,
         long,
+        global = true,
         //default_value = OutputFormat::Table,
-        value_enum,
-        help = "Output format for response",
+        value_parser = OutputFormat::from_str,
+        help = "Output format: table, json, text, yaml, csv or template='{{name}} {{id}}'",
     )]
     pub output: Option<OutputFormat>,
 
+    /// Tenant ID in request context, `[context]` in config or project of token by default
+    #[arg(long, global = true)]
+    pub tenant: Option<String>,
+
+    /// User ID in request context, `[context]` in config or user of token by default
+    #[arg(long, global = true)]
+    pub user: Option<String>,
+
+    /// Send requests without admin in context
+    #[arg(long, global = true)]
+    pub no_admin: bool,
+
+    /// Show all columns of a list in table without truncation
+    #[arg(long, global = true)]
+    pub wide: bool,
+
+    /// Machine-readable mode, nothing but payload on stdout. Other lines go to stderr,
+    /// and an error is printed there as JSON
+    #[arg(long, visible_alias = "porcelain", global = true)]
+    pub quiet: bool,
+
     /// Convert UNIX timestamp to UTC datetime
     #[arg(short, long)]
     pub timestamp: Option<i64>,
@@ -55,9 +80,117 @@ pub struct Opts {
     /// Get API cache
     #[arg(long)]
     pub cache: bool,
+
+    /// Export latency of all requests to a CSV or JSON (by extension) file
+    #[arg(long, global = true)]
+    pub metrics: Option<PathBuf>,
+
+    /// Requests sent at once for an operation on several resources, `parallel` of `[api]` in config by default
+    #[arg(long, global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    pub parallel: Option<u16>,
+
+    /// Profile in config.toml to be used, or set by env SDNCLI_PROFILE
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Config file to be used, or set by env SDNCLI_CONFIG.
+    /// Otherwise look up ~/.config/sdncli, /etc/sdncli and directory of the executable
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+}
+
+/// Options for `cleanup` command
+#[derive(Args)]
+pub struct CleanupOpts {
+    /// Only list resources recorded in ledger without deleting them
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Delete without confirmation
+    #[arg(short, long, conflicts_with = "dry_run")]
+    pub yes: bool,
+}
+
+/// Options for `loadbalance create`
+#[derive(Args)]
+pub struct LbCreateOpts {
+    /// Name of loadbalancer, also used as prefix of its children
+    pub name: String,
+    /// ID or Name of subnet for VIP and members
+    #[arg(short, long)]
+    pub subnet: String,
+    /// Protocol of listener and pool
+    #[arg(long, default_value = "HTTP")]
+    pub protocol: String,
+    /// Port of listener
+    #[arg(long, default_value_t = 80)]
+    pub port: u16,
+    /// Load balancing algorithm of pool
+    #[arg(long, default_value = "ROUND_ROBIN")]
+    pub algorithm: String,
+    /// Member address with optional port, example: --member=10.0.0.10:8080,10.0.0.11,[fd00::10]:8080
+    #[arg(short, long, value_delimiter = ',')]
+    pub member: Vec<String>,
+    /// Health monitor type, example: PING, TCP, HTTP. No monitor if not set
+    #[arg(long)]
+    pub monitor: Option<String>,
+    /// Seconds between health checks
+    #[arg(long, default_value_t = 5, requires = "monitor")]
+    pub delay: u32,
+    /// Seconds to wait for a health check
+    #[arg(long, default_value_t = 5, requires = "monitor")]
+    pub timeout: u32,
+    /// Failed checks before a member is down
+    #[arg(long, default_value_t = 3, requires = "monitor")]
+    pub max_retries: u32,
+    /// Loadbalancer attributes, example: -a vcpus=2
+    #[arg(short, long, value_parser = key_val_parser)]
+    pub attr: Vec<Value>,
+}
+
+// Operations of `loadbalance` command, which handles a loadbalancer with its listener,
+// pool, members and health monitor as a whole. Not a doc comment, since it would replace `about`.
+#[derive(Parser)]
+pub enum LbOperations {
+    /// Create loadbalancer, listener, pool, members and health monitor
+    Create(LbCreateOpts),
+    /// Display loadbalancer(s) with all children as a tree
+    Show {
+        /// ID or Name of loadbalancer(s), example: --names=lb1,lb2
+        #[arg(value_delimiter = ',', required = true)]
+        names: Vec<String>,
+    },
+    /// Display all loadbalancers with their children
+    List,
+    /// Delete loadbalancer(s) with all children
+    Delete {
+        /// ID or Name of loadbalancer(s), example: --names=lb1,lb2
+        #[arg(value_delimiter = ',', required = true)]
+        names: Vec<String>,
+    },
 }
 
-pub const BUILDIN_CMD: [&str; 3] = ["token", "loadbalance", "vgws"];
+/// Options for `topology` command
+#[derive(Args)]
+pub struct TopologyOpts {
+    /// Type or command of the resource to start from, example: port
+    pub res_type: String,
+    /// ID or Name of the resource
+    pub name: String,
+    /// Levels of references to follow
+    #[arg(long, default_value_t = 4)]
+    pub depth: usize,
+    /// Print as a tree, or as dot for Graphviz
+    #[arg(long, value_enum, default_value_t = TopologyFormat::Tree)]
+    pub format: TopologyFormat,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum TopologyFormat {
+    Tree,
+    Dot,
+}
+
+pub const BUILDIN_CMD: [&str; 5] = ["token", "loadbalance", "vgws", "cleanup", "topology"];
 
 pub fn build_cli() -> Result<Command, anyhow::Error> {
     let cmd = command!()
@@ -68,20 +201,38 @@ pub fn build_cli() -> Result<Command, anyhow::Error> {
 }
 
 fn build_dynamic_cli(mut cli: Command) -> Result<Command, anyhow::Error> {
-    let cfg = read_config()?;
+    // Config is generated when it's used, so that `config init` works without it.
+    // A broken one falls back to defaults, so that `config` can still repair it.
+    let resources = match read_config_if_exists() {
+        Ok(Some(cfg)) => cfg.resource,
+        Ok(None) => default_resources(),
+        Err(e) => {
+            eprintln!("Warning: use default commands since config is invalid - {e}");
+            default_resources()
+        }
+    };
 
     for cmd in &BUILDIN_CMD {
         let mut sub = Command::new(cmd).about("Buildin CMD");
         if cmd == &"loadbalance" {
-            sub = Operations::augment_subcommands(sub).subcommand_required(true);
+            sub = LbOperations::augment_subcommands(sub.about("Loadbalancer with all its children"))
+                .subcommand_required(true);
+        }
+        if cmd == &"cleanup" {
+            sub = CleanupOpts::augment_args(sub.about("Delete resources created by sdncli"));
+        }
+        if cmd == &"topology" {
+            // About is set after args, since doc comment of options would replace it
+            sub = TopologyOpts::augment_args(sub)
+                .about("Follow references of a resource and print them as a graph");
         }
         //let sub = SubArgs::augment_args(sub);
         cli = cli.subcommand(sub);
     }
 
     // custom command
-    for res in cfg.resource {
-        let mut sub = Command::new(res.cmd).about(format!("- {}", res.resource));
+    for res in resources {
+        let mut sub = Command::new(res.cmd.clone()).about(format!("- {}", res.resource));
         if res.resource == "member" {
             sub = sub.arg(
                 arg!(
@@ -90,35 +241,43 @@ fn build_dynamic_cli(mut cli: Command) -> Result<Command, anyhow::Error> {
                 .required(true),
             );
         }
-        let sub = Operations::augment_subcommands(sub);
+        let mut sub = Operations::augment_subcommands(sub);
+        for oper in ["create", "update"] {
+            sub = sub.mut_subcommand(oper, |s| {
+                let s = s.mut_arg("attr", |a| a.add(res.attr_completer()));
+                match res.attr_help() {
+                    Some(help) => s.after_help(help),
+                    None => s,
+                }
+            });
+        }
         cli = cli.subcommand(sub);
     }
     Ok(cli)
 }
 
+/// Config is needed before building command line, so look up options for it in raw args.
+pub fn global_from_args(name: &str) -> Option<String> {
+    let flag = format!("--{name}");
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == flag {
+            return args.next();
+        } else if let Some(value) = arg.strip_prefix(&format!("{flag}=")) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
 pub fn cli_matches(matches: &ArgMatches) -> Opts {
     Opts::from_arg_matches(matches)
         .map_err(|err| err.exit())
         .unwrap()
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-pub enum OutputFormat {
-    Table,
-    Json,
-    Text,
-}
-
-impl ToString for OutputFormat {
-    fn to_string(&self) -> String {
-        match self {
-            OutputFormat::Table => String::from("table"),
-            OutputFormat::Json => String::from("json"),
-            OutputFormat::Text => String::from("text"),
-        }
-    }
-}
-
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum Method {
     Post,
@@ -127,13 +286,13 @@ pub enum Method {
     Delete,
 }
 
-impl ToString for Method {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Method::Post => String::from("post"),
-            Method::Put => String::from("put"),
-            Method::Get => String::from("get"),
-            Method::Delete => String::from("delete"),
+            Method::Post => write!(f, "post"),
+            Method::Put => write!(f, "put"),
+            Method::Get => write!(f, "get"),
+            Method::Delete => write!(f, "delete"),
         }
     }
 }
@@ -158,30 +317,75 @@ pub enum Operations {
         /// Example: -a binding:vif_details='{"port_filter":true}'
         #[arg(short, long, value_parser = key_val_parser)]
         attr: Vec<Value>,
+        /// Name matching several resources
+        #[command(flatten)]
+        pick: Pick,
     },
     /// Delete resources
     Delete {
         /// ID or Name of resource(s) to be deleted, example: --names=james,james1
         #[arg(value_delimiter = ',', required = true)]
         names: Vec<String>,
+        /// Also delete what depends on them, like ports, subnets and router interfaces of a network
+        #[arg(long)]
+        cascade: bool,
+        /// Run cascade without confirmation
+        #[arg(short, long, requires = "cascade")]
+        yes: bool,
+        /// Name matching several resources
+        #[command(flatten)]
+        pick: Pick,
     },
     /// Display detail for some resource(s)
     Show {
         /// ID or Name of resource(s) to be displayed, example: --names=james,james1
         #[arg(value_delimiter = ',', required = true)]
         names: Vec<String>,
-        /// Fields to be displayed, example: --field=name,id
+        /// Fields to be displayed, with path and alias, example: --field=name,id,ip=fixed_ips[0].ip_address
         #[arg(short, long, value_delimiter = ',')]
         field: Option<Vec<String>>,
+        /// Request again every SECS seconds (2 by default) and highlight changes
+        #[arg(long, value_name = "SECS", num_args = 0..=1, require_equals = true, default_missing_value = "2", value_parser = clap::value_parser!(u64).range(1..))]
+        watch: Option<u64>,
+        /// Stop watching once expressions hold for all resources, example: --until status=ACTIVE
+        #[arg(long, value_parser = where_parser)]
+        until: Vec<Cond>,
+        /// Name matching several resources
+        #[command(flatten)]
+        pick: Pick,
     },
     /// Display all resources
     List {
         /// JSON format filter, example: --filter='{"id":["6bd0768b-0beb-4b30-9916-a3c445fede1c"],"marker":0,"limit":10}'
         #[arg(long, value_parser = json_parser)]
         filter: Option<Value>,
-        /// Fields to be displayed, example: --field=name,id
+        /// Fields to be displayed, with path and alias, example: --field=name,id,ip=fixed_ips[0].ip_address
         #[arg(short, long, value_delimiter = ',')]
         field: Option<Vec<String>>,
+        /// Expression to filter resources, `*` matches any characters for `=`.
+        /// Example: -w name=web* -w admin_state_up=true -w id~=^6bd0 -w mtu>=1450
+        #[arg(short, long = "where", value_parser = where_parser)]
+        wheres: Vec<Cond>,
+        /// Fetch all pages with marker and limit, and print each page once it arrives.
+        /// JSON is printed one resource per line
+        #[arg(long)]
+        all: bool,
+        /// Number of resources in each page
+        #[arg(long, default_value_t = 500, requires = "all")]
+        page_size: usize,
+        /// Sort by value of a field path, example: --sort-by=fixed_ips[0].ip_address.
+        /// All pages are fetched before printing with `--all`
+        #[arg(long)]
+        sort_by: Option<String>,
+        /// Reverse order of resources
+        #[arg(long)]
+        reverse: bool,
+        /// Request again every SECS seconds (2 by default) and highlight changes
+        #[arg(long, value_name = "SECS", num_args = 0..=1, require_equals = true, default_missing_value = "2", conflicts_with = "all", value_parser = clap::value_parser!(u64).range(1..))]
+        watch: Option<u64>,
+        /// Stop watching once expressions hold for all resources, example: --until status=ACTIVE
+        #[arg(long, value_parser = where_parser)]
+        until: Vec<Cond>,
     },
     /// Set operation for a resource
     Oper {
@@ -192,7 +396,7 @@ pub enum Operations {
         /// Example: -a binding:vif_details='{"port_filter":true}'
         #[arg(short, long, value_parser = key_val_parser)]
         attr: Option<Vec<Value>>,
-        /// Fields to be displayed, example: --field=name,id
+        /// Fields to be displayed, with path and alias, example: --field=name,id,ip=fixed_ips[0].ip_address
         #[arg(short, long, value_delimiter = ',')]
         field: Option<Vec<String>>,
         /// Operation string for resource. Please refer to Rest API doc
@@ -201,7 +405,32 @@ pub enum Operations {
     },
 }
 
+/// Resolving a name which matches several resources, asked on terminal by default.
+/// A name can also be qualified by fq_name, example: default-domain:admin:net1
+#[derive(Args, Clone, Copy)]
+pub struct Pick {
+    /// Take the first resource of a name matching several
+    #[arg(long, conflicts_with = "all")]
+    pub first: bool,
+    /// Apply to all resources of a name matching several
+    #[arg(long)]
+    pub all: bool,
+}
+
 impl self::Operations {
+    pub fn resolve(&self) -> Resolve {
+        match self {
+            Operations::Update { pick, .. }
+            | Operations::Delete { pick, .. }
+            | Operations::Show { pick, .. } => match (pick.first, pick.all) {
+                (true, _) => Resolve::First,
+                (_, true) => Resolve::All,
+                _ => Resolve::Ask,
+            },
+            _ => Resolve::Ask,
+        }
+    }
+
     pub fn oper(&self) -> String {
         match self {
             Operations::Show { .. } => String::from("READ"),
@@ -1,8 +1,28 @@
+pub mod cli;
+pub mod schema;
+
+use crate::rest::output::note;
 use anyhow::{anyhow, Error};
 use log::debug;
 use serde::Deserialize;
 use serde_json::Value;
-use std::{env, fs, io, net::IpAddr, path::PathBuf};
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    net::IpAddr,
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+/// Profile selected by `--profile` or `SDNCLI_PROFILE`
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+/// Config file given by `--config`
+static CONFIG: OnceLock<Option<PathBuf>> = OnceLock::new();
+/// Request context given by `--tenant`, `--user` and `--no-admin`
+static CONTEXT: OnceLock<Context> = OnceLock::new();
+/// Directories of ledger and token cache
+static STATE_DIR: OnceLock<PathBuf> = OnceLock::new();
+static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
 
 #[derive(Deserialize)]
 pub struct FileConfig {
@@ -13,50 +33,197 @@ pub struct FileConfig {
     pub body: Value,
 }
 
+/// Sections in `[profile.<name>]` override the top level ones when the profile is selected
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub api: Rest,
     pub auth: Auth,
+    pub db: Option<Db>,
+    pub inspect: Option<Inspect>,
+    #[serde(default)]
+    pub context: Context,
     pub resource: Vec<Resource>,
 }
 
+/// Request context, `[context]` in config. IDs not set here are taken from token.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Context {
+    pub tenant_id: Option<String>,
+    pub user_id: Option<String>,
+    /// `true` if not set
+    pub is_admin: Option<bool>,
+}
+
+impl Context {
+    /// Values of self, or of other if not set
+    pub fn or(self, other: Context) -> Context {
+        Context {
+            tenant_id: self.tenant_id.or(other.tenant_id),
+            user_id: self.user_id.or(other.user_id),
+            is_admin: self.is_admin.or(other.is_admin),
+        }
+    }
+}
+
+/// Override request context of config, by command line options
+pub fn set_context(context: Context) {
+    let _ = CONTEXT.set(context);
+}
+
+/// Tenant given by `--tenant`
+pub fn cli_tenant() -> Option<&'static str> {
+    CONTEXT.get()?.tenant_id.as_deref()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Db {
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Inspect {
+    pub host: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Rest {
     pub host: Option<String>,
     pub port: u32,
+    /// Requests sent at once for an operation on several resources
+    #[serde(default = "default_parallel")]
+    pub parallel: usize,
+}
+
+fn default_parallel() -> usize {
+    8
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Auth {
     pub host: String,
     pub port: u32,
+    #[serde(default)]
     pub user: String,
+    #[serde(default)]
     pub password: String,
+    /// Project name to be scoped
+    #[serde(default)]
     pub project: String,
     pub version: String,
+    /// Project ID to be scoped, preferred over project name
+    pub project_id: Option<String>,
+    /// Domain name of user for v3, `Default` if not set
+    pub user_domain: Option<String>,
+    /// Domain name of project for v3, `Default` if not set
+    pub project_domain: Option<String>,
+    /// Application credential for v3, used instead of user and password
+    pub application_credential_id: Option<String>,
+    pub application_credential_secret: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Resource {
     pub cmd: String,
     #[serde(rename = "type")]
     pub resource: String,
     pub uri: String,
-    #[allow(dead_code)]
-    pub attr: Option<toml::Value>,
+    /// Known attributes, see `schema`
+    #[serde(default)]
+    pub attr: Vec<Attr>,
+    /// Columns of `list` in table without `--field`, picked from resources if empty
+    #[serde(default)]
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Attr {
+    pub key: String,
+    /// Example value of the type
+    pub value: toml::Value,
+    /// Filled when creating if it's set
+    #[serde(default)]
+    pub default: Option<toml::Value>,
+}
+
+/// Select a profile. Environment `SDNCLI_PROFILE` is used if `name` is none.
+pub fn set_profile(name: Option<String>) {
+    let name = name.or_else(|| env::var("SDNCLI_PROFILE").ok().filter(|p| !p.is_empty()));
+    let _ = PROFILE.set(name);
+}
+
+fn profile() -> Option<&'static str> {
+    PROFILE
+        .get_or_init(|| env::var("SDNCLI_PROFILE").ok().filter(|p| !p.is_empty()))
+        .as_deref()
+}
+
+impl Config {
+    /// Host for REST API, use auth host if it is not set
+    pub fn api_host(&self) -> &str {
+        self.api.host.as_deref().unwrap_or(&self.auth.host)
+    }
+
+    /// Request context of command line options, then of config
+    pub fn context(&self) -> Context {
+        CONTEXT
+            .get()
+            .cloned()
+            .unwrap_or_default()
+            .or(self.context.clone())
+    }
+}
+
+/// URI configured for a resource type, `/neutron/<type>` if not found
+pub fn uri_of(resources: &[Resource], res_type: &str) -> String {
+    resources
+        .iter()
+        .find(|r| r.resource == res_type)
+        .map(|r| r.uri.clone())
+        .unwrap_or(format!("/neutron/{res_type}"))
+}
+
+fn parse_config(content: &str, profile: Option<&str>) -> Result<Config, Error> {
+    let mut config: toml::Value = toml::from_str(content)?;
+    if let Some(name) = profile {
+        let selected = config
+            .get("profile")
+            .and_then(|p| p.get(name))
+            .cloned()
+            .ok_or_else(|| anyhow!("Profile `{}` not found in config.toml", name))?;
+        merge(&mut config, selected);
+    }
+    Ok(config.try_into()?)
+}
+
+fn merge(base: &mut toml::Value, other: toml::Value) {
+    match (base, other) {
+        (toml::Value::Table(base), toml::Value::Table(other)) => {
+            for (k, v) in other {
+                match base.get_mut(&k) {
+                    Some(b) => merge(b, v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
 }
 
 pub fn read_config() -> Result<Config, Error> {
-    match std::fs::read_to_string(get_name(FileType::Toml).unwrap()) {
+    match std::fs::read_to_string(get_name(FileType::Toml)?) {
         Ok(content) => {
-            let config: Config = toml::from_str(&content)?;
+            let config = parse_config(&content, profile())?;
             debug!("{:#?}", config);
             Ok(config)
         }
         Err(_) => {
-            gen_config()?;
-            println!("Config file `config.toml` generated in directory same as the executable.");
-            println!("- You can add command or change Api IP in config.toml\n");
+            let path = gen_config()?;
+            note(format!("Config file {:?} generated.", path));
+            note("- You can add command or change Api IP in config.toml\n");
             //println!("<Press Enter to continue ...>");
             //let mut buf = String::new();
             //io::stdin().read_line(&mut buf)?;
@@ -86,11 +253,34 @@ pub fn read_json_file(file: &PathBuf) -> Result<FileConfig, Error> {
 //    }
 //}
 
-//fn gen_config() -> io::Result<()> {
-fn gen_config() -> Result<(), Error> {
+/// Read config if it exists, without generating it
+pub fn read_config_if_exists() -> Result<Option<Config>, Error> {
+    if get_name(FileType::Toml)?.is_file() {
+        Ok(Some(read_config()?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Resources in the generated config
+pub fn default_resources() -> Vec<Resource> {
+    template("127.0.0.1")["resource"]
+        .clone()
+        .try_into()
+        .unwrap()
+}
+
+/// Generate config in the first writable location and return its path
+fn gen_config() -> Result<PathBuf, Error> {
     let vip = std::env::var("CONFIG_API_VIP");
     let ip = match vip {
         Ok(ip) => ip,
+        Err(_) if !io::stdin().is_terminal() => {
+            return Err(anyhow!(
+                "No config.toml found. Run `sdncli config init --host <IP>` \
+                 or set env CONFIG_API_VIP to generate it"
+            ));
+        }
         Err(_) => {
             // Get IP from stdin user input
             let mut ip = String::new();
@@ -101,8 +291,12 @@ fn gen_config() -> Result<(), Error> {
     };
 
     ip.parse::<IpAddr>()?;
+    write_config(&template(&ip), false)
+}
 
-    let config_toml = toml::toml! {
+/// Default config with API IP
+pub fn template(ip: &str) -> toml::Value {
+    toml::Value::Table(toml::toml! {
         [auth]
         host = ip
         port = 6000
@@ -110,6 +304,8 @@ fn gen_config() -> Result<(), Error> {
         password = "ArcherAdmin@123"
         project = "ArcherAdmin"
         version = "v2"
+        user_domain = "Default"
+        project_domain = "Default"
 
         [api]
         port = 8082
@@ -118,6 +314,7 @@ fn gen_config() -> Result<(), Error> {
         cmd = "net"
         type = "network"
         uri = "/neutron/network"
+        columns = ["id", "name", "status", "admin_state_up", "subnets"]
         attr = [
             { key="provider:segmentation_id", value=0 },
             { key="router:external", value=true },
@@ -129,16 +326,19 @@ fn gen_config() -> Result<(), Error> {
         cmd = "subnet"
         type = "subnet"
         uri = "/neutron/subnet"
+        columns = ["id", "name", "network_id", "cidr", "gateway_ip"]
 
         [[resource]]
         cmd = "port"
         type = "port"
         uri = "/neutron/port"
+        columns = ["id", "name", "status", "mac_address", "ip=fixed_ips[0].ip_address", "device_owner"]
 
         [[resource]]
         cmd = "router"
         type = "router"
         uri = "/neutron/router"
+        columns = ["id", "name", "status", "admin_state_up"]
 
         [[resource]]
         cmd = "sg"
@@ -154,11 +354,13 @@ fn gen_config() -> Result<(), Error> {
         cmd = "fip"
         type = "floatingip"
         uri = "/neutron/floatingip"
+        columns = ["id", "floating_ip_address", "fixed_ip_address", "status", "port_id"]
 
         [[resource]]
         cmd = "lb"
         type = "loadbalancer"
         uri = "/neutron/loadbalancer"
+        columns = ["id", "name", "vip_address", "provisioning_status", "operating_status"]
         attr = [
             { key="vip_subnet_id", value="" },
             { key="vcpus", value=0 },
@@ -219,9 +421,11 @@ fn gen_config() -> Result<(), Error> {
         cmd = "provider"
         type = "net_provider"
         uri = "/neutron/net_provider"
-    };
+    })
+}
 
-    let json5_str = r#"{
+/// Example for `--file`
+const RESOURCE_JSON: &str = r#"{
 //"port": 8081,
 //"method": "get",
 "method": "post",
@@ -260,27 +464,179 @@ fn gen_config() -> Result<(), Error> {
 
 }
 "#;
-    fs::write(
-        get_name(FileType::Toml)?,
-        toml::to_string(&config_toml).unwrap(),
-    )?;
-    let path = get_name(FileType::Json)?;
-    if !path.exists() {
-        fs::write(get_name(FileType::Json)?, json5_str)?;
+
+/// Path of config file in use, or none if it does not exist
+pub fn config_file() -> io::Result<Option<PathBuf>> {
+    let path = get_name(FileType::Toml)?;
+    Ok(path.is_file().then_some(path))
+}
+
+/// Write config to the file in use, or the first writable location if there is none yet.
+/// Return path of the config file.
+pub fn write_config(config: &toml::Value, overwrite: bool) -> Result<PathBuf, Error> {
+    let path = match config_file()? {
+        Some(path) if overwrite => path,
+        Some(path) => return Err(anyhow!("Config {:?} already exists", path)),
+        None => config_candidates()?
+            .into_iter()
+            .find(|p| p.parent().is_some_and(is_writable))
+            .ok_or_else(|| anyhow!("No writable location for config.toml"))?,
+    };
+    fs::write(&path, toml::to_string(config)?)?;
+    let json = get_name(FileType::Json)?;
+    if !json.exists() {
+        fs::write(json, RESOURCE_JSON)?;
     }
-    Ok(())
+    Ok(path)
+}
+
+/// Use the config file given by `--config`, or environment `SDNCLI_CONFIG` if it's none.
+pub fn set_config(path: Option<PathBuf>) {
+    let _ = CONFIG.set(path);
+}
+
+/// `$<var>/sdncli` of XDG base directory, or `~/<default>/sdncli` if it's not set
+fn xdg_dir(var: &str, default: &str) -> Option<PathBuf> {
+    let base = match env::var_os(var).filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").filter(|d| !d.is_empty())?).join(default),
+    };
+    Some(base.join("sdncli"))
+}
+
+/// `$XDG_CONFIG_HOME/sdncli` or `~/.config/sdncli`
+fn user_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+fn exe_dir() -> io::Result<PathBuf> {
+    let mut path = env::current_exe()?;
+    path.pop();
+    Ok(path)
+}
+
+/// Config files in lookup order. Only the explicit one is used if it's given.
+fn config_candidates() -> io::Result<Vec<PathBuf>> {
+    if let Some(Some(path)) = CONFIG.get() {
+        return Ok(vec![path.clone()]);
+    }
+    if let Some(path) = env::var_os("SDNCLI_CONFIG").filter(|p| !p.is_empty()) {
+        return Ok(vec![PathBuf::from(path)]);
+    }
+    let mut paths = Vec::new();
+    if let Some(dir) = user_dir() {
+        paths.push(dir.join("config.toml"));
+    }
+    paths.push(PathBuf::from("/etc/sdncli/config.toml"));
+    paths.push(exe_dir()?.join("config.toml"));
+    Ok(paths)
+}
+
+/// Create directory if it does not exist, and check if a file can be written into it.
+fn is_writable(dir: &std::path::Path) -> bool {
+    if fs::create_dir_all(dir).is_err() {
+        return false;
+    }
+    let probe = dir.join(format!(".sdncli-{}", std::process::id()));
+    match fs::write(&probe, b"") {
+        Ok(_) => fs::remove_file(probe).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// XDG directory for files written by sdncli, or directory of the executable if it's not
+/// writable. It's checked once in a process.
+fn data_dir(dir: &'static OnceLock<PathBuf>, var: &str, default: &str) -> io::Result<PathBuf> {
+    if let Some(dir) = dir.get() {
+        return Ok(dir.clone());
+    }
+    let found = match xdg_dir(var, default).filter(|d| is_writable(d)) {
+        Some(found) => found,
+        None => exe_dir()?,
+    };
+    Ok(dir.get_or_init(|| found).clone())
 }
 
 enum FileType {
     Json,
     Toml,
+    Ledger,
+    Token,
+}
+
+/// File to record resources created by sdncli
+pub fn ledger_file() -> io::Result<PathBuf> {
+    get_name(FileType::Ledger)
+}
+
+/// File to cache tokens across invocations
+pub fn token_file() -> io::Result<PathBuf> {
+    get_name(FileType::Token)
 }
 
 fn get_name(ftype: FileType) -> io::Result<PathBuf> {
-    let mut path = env::current_exe()?;
-    match ftype {
-        FileType::Toml => path.set_file_name("config.toml"),
-        FileType::Json => path.set_file_name("resource.json"),
+    let config = || -> io::Result<PathBuf> {
+        let candidates = config_candidates()?;
+        Ok(candidates
+            .iter()
+            .find(|p| p.is_file())
+            .unwrap_or(&candidates[0])
+            .clone())
+    };
+    Ok(match ftype {
+        FileType::Toml => config()?,
+        FileType::Json => config()?.with_file_name("resource.json"),
+        FileType::Ledger => {
+            data_dir(&STATE_DIR, "XDG_STATE_HOME", ".local/state")?.join("ledger.jsonl")
+        }
+        FileType::Token => data_dir(&CACHE_DIR, "XDG_CACHE_HOME", ".cache")?.join("token.json"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let content = r#"
+            [auth]
+            host = "10.0.0.1"
+            port = 6000
+            user = "admin"
+            password = "admin"
+            project = "admin"
+            version = "v2"
+
+            [api]
+            port = 8082
+
+            [profile.lab.auth]
+            host = "10.0.1.1"
+            version = "v3"
+
+            [profile.lab.db]
+            hosts = ["10.0.1.2"]
+
+            [profile.lab.api]
+            parallel = 16
+
+            [[resource]]
+            cmd = "net"
+            type = "network"
+            uri = "/neutron/network"
+        "#;
+        let config = parse_config(content, None).unwrap();
+        assert_eq!(config.api_host(), "10.0.0.1");
+        assert_eq!(config.api.parallel, 8);
+        assert!(config.db.is_none());
+
+        let config = parse_config(content, Some("lab")).unwrap();
+        assert_eq!(config.api_host(), "10.0.1.1");
+        assert_eq!(config.auth.version, "v3");
+        assert_eq!(config.auth.user, "admin");
+        assert_eq!(config.db.unwrap().hosts, ["10.0.1.2"]);
+        assert_eq!((config.api.port, config.api.parallel), (8082, 16));
+        assert!(parse_config(content, Some("prod")).is_err());
     }
-    Ok(path)
 }
@@ -0,0 +1,428 @@
+use super::{config_file, parse_config, template, write_config, Config};
+use crate::rest::output::note;
+use anyhow::{anyhow, Error};
+use clap::{ArgMatches, Args, Command, FromArgMatches, Subcommand};
+use std::{collections::HashSet, fs, net::IpAddr};
+
+/// Keys with secrets which are not displayed
+const SECRET_KEYS: [&str; 2] = ["password", "application_credential_secret"];
+/// Keys of string fields, whose values are never parsed as TOML, e.g. a password `123456`
+const STRING_KEYS: [&str; 14] = [
+    "api.host",
+    "auth.host",
+    "auth.user",
+    "auth.password",
+    "auth.project",
+    "auth.version",
+    "auth.project_id",
+    "auth.user_domain",
+    "auth.project_domain",
+    "auth.application_credential_id",
+    "auth.application_credential_secret",
+    "inspect.host",
+    "context.tenant_id",
+    "context.user_id",
+];
+
+#[derive(Subcommand)]
+enum ConfigCmd {
+    /// Generate config.toml without prompt
+    Init(InitOpts),
+    /// Display config with secrets redacted
+    Show,
+    /// Set values by dotted keys, example: auth.host=10.0.0.1 profile.lab.api.port=8082
+    Set {
+        #[arg(required = true, value_parser = key_val_parser)]
+        items: Vec<(String, String)>,
+    },
+    /// Check config and all its profiles
+    Validate,
+    /// Add a `[[resource]]` entry as a new command
+    AddResource {
+        /// Command name
+        #[arg(long)]
+        cmd: String,
+        /// Resource type in API
+        #[arg(long = "type")]
+        resource: String,
+        /// API URI, `/neutron/<type>` by default
+        #[arg(long)]
+        uri: Option<String>,
+        /// Attribute with an example value of its type, example: -a router:external=true
+        #[arg(short, long, value_parser = key_val_parser)]
+        attr: Vec<(String, String)>,
+        /// Default value of an attribute filled when creating, example: --default admin_state_up=true
+        #[arg(long, value_parser = key_val_parser)]
+        default: Vec<(String, String)>,
+        /// Columns of list in table, example: --columns=id,name,ip=fixed_ips[0].ip_address
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<String>,
+    },
+    /// Remove `[[resource]]` entries by command name
+    RemoveResource {
+        #[arg(required = true)]
+        cmds: Vec<String>,
+    },
+}
+
+#[derive(Args)]
+struct InitOpts {
+    /// IP of auth and API
+    #[arg(long)]
+    host: IpAddr,
+    /// API host if it's different from auth host
+    #[arg(long)]
+    api_host: Option<String>,
+    #[arg(long, default_value_t = 8082)]
+    api_port: u32,
+    #[arg(long, default_value_t = 6000)]
+    auth_port: u32,
+    #[arg(long)]
+    user: Option<String>,
+    #[arg(long)]
+    password: Option<String>,
+    #[arg(long)]
+    project: Option<String>,
+    /// Keystone version: v2 or v3
+    #[arg(long)]
+    version: Option<String>,
+    #[arg(long)]
+    user_domain: Option<String>,
+    #[arg(long)]
+    project_domain: Option<String>,
+    #[arg(long)]
+    project_id: Option<String>,
+    #[arg(long)]
+    application_credential_id: Option<String>,
+    #[arg(long)]
+    application_credential_secret: Option<String>,
+    /// Overwrite existing config
+    #[arg(long)]
+    force: bool,
+}
+
+pub fn build_cli(cmd: Command) -> Command {
+    let cli = Command::new("config")
+        .about("Manage config.toml")
+        .subcommand_required(true);
+    let cli = ConfigCmd::augment_subcommands(cli);
+    cmd.subcommand(cli)
+}
+
+pub async fn handle_cli(matches: &ArgMatches) -> Result<bool, anyhow::Error> {
+    let Some(matches) = matches.subcommand_matches("config") else {
+        return Ok(false);
+    };
+    let cmd = ConfigCmd::from_arg_matches(matches)
+        .map_err(|err| err.exit())
+        .unwrap();
+    match cmd {
+        ConfigCmd::Init(opts) => init(opts)?,
+        ConfigCmd::Show => show()?,
+        ConfigCmd::Set { items } => {
+            let mut config = load()?;
+            for (key, value) in items {
+                set(&mut config, &key, parse_value_of(&key, &value))?;
+            }
+            check(&config)?;
+            save(&config)?;
+        }
+        ConfigCmd::Validate => {
+            check(&load()?)?;
+            println!("OK");
+        }
+        ConfigCmd::AddResource {
+            cmd,
+            resource,
+            uri,
+            attr,
+            default,
+            columns,
+        } => {
+            let mut config = load()?;
+            let mut entry = toml::Table::new();
+            entry.insert(String::from("cmd"), toml::Value::String(cmd));
+            entry.insert(
+                String::from("uri"),
+                uri.unwrap_or(format!("/neutron/{resource}")).into(),
+            );
+            entry.insert(String::from("type"), toml::Value::String(resource));
+            let mut attrs: Vec<toml::Table> = attr
+                .into_iter()
+                .map(|(k, v)| {
+                    let mut a = toml::Table::new();
+                    a.insert(String::from("key"), toml::Value::String(k));
+                    a.insert(String::from("value"), parse_value(&v));
+                    a
+                })
+                .collect();
+            for (k, v) in default {
+                let key = toml::Value::String(k.clone());
+                match attrs.iter_mut().find(|a| a.get("key") == Some(&key)) {
+                    Some(a) => {
+                        a.insert(String::from("default"), parse_value(&v));
+                    }
+                    // Default also tells the type
+                    None => attrs.push(toml::Table::from_iter([
+                        (String::from("key"), key),
+                        (String::from("value"), parse_value(&v)),
+                        (String::from("default"), parse_value(&v)),
+                    ])),
+                }
+            }
+            if !attrs.is_empty() {
+                let attrs = attrs.into_iter().map(toml::Value::Table).collect();
+                entry.insert(String::from("attr"), toml::Value::Array(attrs));
+            }
+            if !columns.is_empty() {
+                entry.insert(String::from("columns"), columns.into());
+            }
+            resources(&mut config)?.push(toml::Value::Table(entry));
+            check(&config)?;
+            save(&config)?;
+        }
+        ConfigCmd::RemoveResource { mut cmds } => {
+            cmds.sort();
+            cmds.dedup();
+            let mut config = load()?;
+            let list = resources(&mut config)?;
+            let len = list.len();
+            list.retain(|r| {
+                !cmds
+                    .iter()
+                    .any(|c| r.get("cmd").and_then(|v| v.as_str()) == Some(c))
+            });
+            if list.len() + cmds.len() != len {
+                return Err(anyhow!("Some of {:?} are not found", cmds));
+            }
+            save(&config)?;
+        }
+    }
+    Ok(true)
+}
+
+fn init(opts: InitOpts) -> Result<(), Error> {
+    let mut config = template(&opts.host.to_string());
+    set(
+        &mut config,
+        "api.port",
+        toml::Value::Integer(opts.api_port.into()),
+    )?;
+    set(
+        &mut config,
+        "auth.port",
+        toml::Value::Integer(opts.auth_port.into()),
+    )?;
+    let optional = [
+        ("api.host", opts.api_host),
+        ("auth.user", opts.user),
+        ("auth.password", opts.password),
+        ("auth.project", opts.project),
+        ("auth.version", opts.version),
+        ("auth.user_domain", opts.user_domain),
+        ("auth.project_domain", opts.project_domain),
+        ("auth.project_id", opts.project_id),
+        (
+            "auth.application_credential_id",
+            opts.application_credential_id,
+        ),
+        (
+            "auth.application_credential_secret",
+            opts.application_credential_secret,
+        ),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            set(&mut config, key, toml::Value::String(value))?;
+        }
+    }
+    check(&config)?;
+    let path = write_config(&config, opts.force)?;
+    note(format!("Config file {:?} generated.", path));
+    Ok(())
+}
+
+fn show() -> Result<(), Error> {
+    let path = config_file()?.ok_or_else(|| anyhow!("No config.toml found"))?;
+    let mut config = load()?;
+    redact(&mut config);
+    println!("# {}", path.display());
+    print!("{}", toml::to_string(&config)?);
+    Ok(())
+}
+
+fn load() -> Result<toml::Value, Error> {
+    let path = config_file()?
+        .ok_or_else(|| anyhow!("No config.toml found, run `sdncli config init` first"))?;
+    Ok(toml::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn save(config: &toml::Value) -> Result<(), Error> {
+    let path = write_config(config, true)?;
+    note(format!("Config file {:?} updated.", path));
+    Ok(())
+}
+
+fn resources(config: &mut toml::Value) -> Result<&mut Vec<toml::Value>, Error> {
+    config
+        .as_table_mut()
+        .unwrap()
+        .entry("resource")
+        .or_insert(toml::Value::Array(Vec::new()))
+        .as_array_mut()
+        .ok_or_else(|| anyhow!("`resource` should be an array"))
+}
+
+fn redact(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (k, v) in table.iter_mut() {
+                if SECRET_KEYS.contains(&k.as_str()) {
+                    *v = toml::Value::String(String::from("******"));
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        toml::Value::Array(array) => array.iter_mut().for_each(redact),
+        _ => (),
+    }
+}
+
+/// Set value by dotted key path, tables are created if not exist
+fn set(config: &mut toml::Value, key: &str, value: toml::Value) -> Result<(), Error> {
+    let mut keys: Vec<&str> = key.split('.').collect();
+    let last = keys
+        .pop()
+        .filter(|k| !k.is_empty())
+        .ok_or_else(|| anyhow!("Empty key"))?;
+    let mut table = config.as_table_mut().unwrap();
+    for k in keys {
+        table = table
+            .entry(k)
+            .or_insert(toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("`{}` in `{}` is not a table", k, key))?;
+    }
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+
+/// Value of a key, as a string for string fields, otherwise by `parse_value`
+fn parse_value_of(key: &str, s: &str) -> toml::Value {
+    // Same fields in a profile, e.g. `profile.lab.auth.password`
+    let field = key
+        .strip_prefix("profile.")
+        .and_then(|k| k.split_once('.'))
+        .map_or(key, |(_, k)| k);
+    if STRING_KEYS.contains(&field) {
+        toml::Value::String(s.to_string())
+    } else {
+        parse_value(s)
+    }
+}
+
+/// Value in TOML syntax, or a plain string
+fn parse_value(s: &str) -> toml::Value {
+    match toml::from_str::<toml::Table>(&format!("v = {s}")) {
+        Ok(mut t) => t.remove("v").unwrap(),
+        Err(_) => toml::Value::String(s.to_string()),
+    }
+}
+
+fn key_val_parser(s: &str) -> Result<(String, String), String> {
+    let pos = s
+        .find('=')
+        .ok_or_else(|| format!("invalid KEY=value: no `=` found in `{s}`"))?;
+    Ok((s[..pos].to_string(), s[pos + 1..].to_string()))
+}
+
+/// Check config with all profiles applied
+fn check(config: &toml::Value) -> Result<(), Error> {
+    let content = toml::to_string(config)?;
+    let mut profiles: Vec<Option<&str>> = vec![None];
+    if let Some(p) = config.get("profile").and_then(|p| p.as_table()) {
+        profiles.extend(p.keys().map(|k| Some(k.as_str())));
+    }
+    let mut errors = Vec::new();
+    for profile in profiles {
+        let name = profile.map_or(String::new(), |p| format!("profile `{p}`: "));
+        match parse_config(&content, profile) {
+            Ok(cfg) => errors.extend(problems(&cfg).into_iter().map(|e| format!("{name}{e}"))),
+            Err(e) => errors.push(format!("{name}{e}")),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid config:\n  {}", errors.join("\n  ")))
+    }
+}
+
+fn problems(cfg: &Config) -> Vec<String> {
+    let mut errors = Vec::new();
+    if !["v2", "v3"].contains(&cfg.auth.version.to_lowercase().as_str()) {
+        errors.push(format!(
+            "auth.version `{}` should be v2 or v3",
+            cfg.auth.version
+        ));
+    }
+    if cfg.auth.application_credential_id.is_some()
+        != cfg.auth.application_credential_secret.is_some()
+    {
+        errors.push(String::from(
+            "auth.application_credential_id and secret should be set together",
+        ));
+    } else if cfg.auth.application_credential_id.is_none() && cfg.auth.user.is_empty() {
+        errors.push(String::from("auth.user is not set"));
+    }
+    let mut cmds = HashSet::new();
+    for res in &cfg.resource {
+        if !cmds.insert(&res.cmd) {
+            errors.push(format!("resource cmd `{}` is duplicated", res.cmd));
+        }
+        if !res.uri.starts_with('/') {
+            errors.push(format!(
+                "resource `{}` uri `{}` should start with `/`",
+                res.cmd, res.uri
+            ));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set() {
+        let mut config = template("10.0.0.1");
+        set(&mut config, "auth.port", parse_value("5000")).unwrap();
+        set(
+            &mut config,
+            "profile.lab.auth.host",
+            parse_value("10.0.1.1"),
+        )
+        .unwrap();
+        assert_eq!(config["auth"]["port"].as_integer(), Some(5000));
+        assert_eq!(
+            config["profile"]["lab"]["auth"]["host"].as_str(),
+            Some("10.0.1.1")
+        );
+        assert!(set(&mut config, "auth.port.x", parse_value("1")).is_err());
+        let password = parse_value_of("auth.password", "123456");
+        set(&mut config, "auth.password", password).unwrap();
+        assert_eq!(config["auth"]["password"].as_str(), Some("123456"));
+        let password = parse_value_of("profile.lab.auth.password", "123456");
+        assert_eq!(password.as_str(), Some("123456"));
+        assert_eq!(
+            parse_value_of("profile.lab.api.port", "8082").as_integer(),
+            Some(8082)
+        );
+        assert!(check(&config).is_ok());
+
+        redact(&mut config);
+        assert_eq!(config["auth"]["password"].as_str(), Some("******"));
+    }
+}
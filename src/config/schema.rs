@@ -0,0 +1,186 @@
+//! `[[resource]].attr` lists attributes known for a resource. `value` is only an example of
+//! its type, used to check `-a key=value`. `default` is filled when creating, if it's set:
+//!
+//! ```toml
+//! attr = [
+//!     { key="router:external", value=true },
+//!     { key="admin_state_up", value=true, default=true },
+//!     { key="subnets", value=[""] },
+//! ]
+//! ```
+use super::{Attr, Resource};
+use anyhow::{anyhow, Error};
+use clap::builder::StyledStr;
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use serde_json::{Map, Value};
+
+fn type_name(v: &toml::Value) -> &'static str {
+    match v {
+        toml::Value::String(_) | toml::Value::Datetime(_) => "string",
+        toml::Value::Integer(_) => "integer",
+        toml::Value::Float(_) => "float",
+        toml::Value::Boolean(_) => "boolean",
+        toml::Value::Array(_) => "list",
+        toml::Value::Table(_) => "dict",
+    }
+}
+
+/// Check a value against the type of `schema`, numbers and booleans are accepted as string.
+fn conform(schema: &toml::Value, value: Value) -> Result<Value, Value> {
+    match (schema, value) {
+        (toml::Value::String(_) | toml::Value::Datetime(_), v) => match v {
+            Value::String(_) => Ok(v),
+            Value::Number(n) => Ok(Value::String(n.to_string())),
+            Value::Bool(b) => Ok(Value::String(b.to_string())),
+            v => Err(v),
+        },
+        (toml::Value::Integer(_), v) if v.is_i64() || v.is_u64() => Ok(v),
+        (toml::Value::Float(_), v) if v.is_number() => Ok(v),
+        (toml::Value::Boolean(_), v) if v.is_boolean() => Ok(v),
+        (toml::Value::Table(_), v) if v.is_object() => Ok(v),
+        (toml::Value::Array(a), Value::Array(values)) => match a.first() {
+            Some(item) => values
+                .into_iter()
+                .map(|v| conform(item, v))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            None => Ok(Value::Array(values)),
+        },
+        (_, v) => Err(v),
+    }
+}
+
+fn to_json(v: &toml::Value) -> Value {
+    serde_json::to_value(v).unwrap_or_default()
+}
+
+impl Attr {
+    fn describe(&self) -> String {
+        match &self.default {
+            Some(v) => format!("{}, default {}", type_name(&self.value), to_json(v)),
+            None => type_name(&self.value).to_string(),
+        }
+    }
+}
+
+impl Resource {
+    /// Check attributes given by user and fill defaults if `create`.
+    /// Unknown attributes are still sent, with a warning.
+    pub fn apply_attr(&self, attr: &mut Map<String, Value>, create: bool) -> Result<(), Error> {
+        if self.attr.is_empty() {
+            return Ok(());
+        }
+        for (key, value) in attr.iter_mut() {
+            let Some(schema) = self.attr.iter().find(|a| &a.key == key) else {
+                eprintln!(
+                    "Warning: `{}` is not a known attribute of {}, known: {}",
+                    key,
+                    self.resource,
+                    self.attr
+                        .iter()
+                        .map(|a| a.key.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                continue;
+            };
+            *value = conform(&schema.value, value.take()).map_err(|v| {
+                anyhow!(
+                    "Attribute `{}` of {} should be {}, not `{}`",
+                    key,
+                    self.resource,
+                    type_name(&schema.value),
+                    v
+                )
+            })?;
+        }
+        if create {
+            for a in &self.attr {
+                if let (false, Some(v)) = (attr.contains_key(&a.key), &a.default) {
+                    attr.insert(a.key.clone(), to_json(v));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Attributes displayed in help of `create` and `update`
+    pub fn attr_help(&self) -> Option<String> {
+        if self.attr.is_empty() {
+            return None;
+        }
+        let width = self.attr.iter().map(|a| a.key.len()).max().unwrap_or(0);
+        let lines: Vec<_> = self
+            .attr
+            .iter()
+            .map(|a| format!("  {:width$}  {}", a.key, a.describe()))
+            .collect();
+        Some(format!("Attributes:\n{}", lines.join("\n")))
+    }
+
+    /// Complete `-a` with `key=` of known attributes
+    pub fn attr_completer(&self) -> ArgValueCompleter {
+        let attr = self.attr.clone();
+        ArgValueCompleter::new(move |current: &std::ffi::OsStr| {
+            let current = current.to_string_lossy();
+            attr.iter()
+                .filter(|a| a.key.starts_with(current.as_ref()))
+                .map(|a| {
+                    CompletionCandidate::new(format!("{}=", a.key))
+                        .help(Some(StyledStr::from(a.describe())))
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_attr() {
+        let res: Resource = toml::from_str(
+            r#"
+            cmd = "net"
+            type = "network"
+            uri = "/neutron/network"
+            attr = [
+                { key="provider:segmentation_id", value=0 },
+                { key="router:external", value=true },
+                { key="admin_state_up", value=true, default=true },
+                { key="provider:network_type", value="" },
+                { key="subnets", value=[""] },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        // Only explicit defaults are filled, values are examples of types
+        let mut attr = json!({"provider:network_type": 100, "description": "x"});
+        res.apply_attr(attr.as_object_mut().unwrap(), true).unwrap();
+        assert_eq!(
+            attr,
+            json!({
+                "provider:network_type": "100",
+                "admin_state_up": true,
+                "description": "x",
+            })
+        );
+        let mut attr = json!({"admin_state_up": false});
+        res.apply_attr(attr.as_object_mut().unwrap(), true).unwrap();
+        assert_eq!(attr, json!({"admin_state_up": false}));
+        assert_eq!(res.attr[1].describe(), "boolean");
+        assert_eq!(res.attr[2].describe(), "boolean, default true");
+
+        let mut attr = json!({"router:external": "yes"});
+        assert!(res
+            .apply_attr(attr.as_object_mut().unwrap(), false)
+            .is_err());
+        let mut attr = json!({"subnets": [1]});
+        res.apply_attr(attr.as_object_mut().unwrap(), false)
+            .unwrap();
+        assert_eq!(attr, json!({"subnets": ["1"]}));
+    }
+}
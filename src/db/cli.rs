@@ -1,20 +1,22 @@
+use crate::config::read_config;
+use crate::rest::output::{note, print_output, OutputFormat};
 use anyhow;
-use clap::{ArgMatches, Args, Command, FromArgMatches};
+use clap::{ArgGroup, ArgMatches, Args, Command, FromArgMatches};
 use scylla::{transport::Compression, IntoTypedRows, Session, SessionBuilder};
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::{collections::HashSet, time::Duration, time::Instant};
 
 use super::cql::cqlsh;
 
 #[derive(Args)]
+#[command(group = ArgGroup::new("arg").required(true))]
 struct Opts {
-    /// DB hosts to be connected
-    #[arg(required = true, requires = "arg")]
+    /// DB hosts to be connected, `[db]` or API host in config.toml by default
     hosts: Vec<String>,
 
-    /// DB port to be connected
-    #[arg(short, long, default_value_t = 9041)]
-    port: u16,
+    /// DB port to be connected, `[db]` in config.toml or 9041 by default
+    #[arg(short, long)]
+    port: Option<u16>,
 
     /// Show resouce's properties
     #[arg(short, long, group = "arg")]
@@ -52,12 +54,20 @@ pub async fn handle_cli(matches: &ArgMatches) -> Result<(), anyhow::Error> {
         let cmd = Opts::from_arg_matches(matches)
             .map_err(|err| err.exit())
             .unwrap();
-        let nodes: Vec<_> = cmd
-            .hosts
+        let fmt = matches.get_one::<OutputFormat>("output");
+        let cfg = read_config()?;
+        let db = cfg.db.clone();
+        let hosts = match (&cmd.hosts, &db) {
+            (hosts, _) if !hosts.is_empty() => hosts.clone(),
+            (_, Some(db)) if !db.hosts.is_empty() => db.hosts.clone(),
+            _ => vec![cfg.api_host().to_string()],
+        };
+        let port = cmd.port.or(db.and_then(|db| db.port)).unwrap_or(9041);
+        let nodes: Vec<_> = hosts
             .iter()
-            .map(|item| format!("{item}:{0}", cmd.port))
+            .map(|item| format!("{item}:{port}"))
             .collect();
-        println!("** Connecting to {nodes:?} ...");
+        note(format!("** Connecting to {nodes:?} ..."));
 
         let session: Session = SessionBuilder::new()
             .known_nodes(nodes)
@@ -68,74 +78,96 @@ pub async fn handle_cli(matches: &ArgMatches) -> Result<(), anyhow::Error> {
             .build()
             .await?;
 
-        println!("** DB connected, ready to send query ...");
+        note("** DB connected, ready to send query ...");
 
         if let Some(uuid) = cmd.uuid {
             let q: &str = r#"SELECT blobAsText(column1), value, WRITETIME(value)
                              FROM config_db_uuid.obj_uuid_table
                              WHERE key = textAsBlob(?)"#;
-            query(&session, q, &uuid).await?;
+            query(&session, q, &uuid, fmt).await?;
         }
 
         if let Some(fqname) = cmd.fqname {
             let q: &str = r#"SELECT blobAsText(column1), value, WRITETIME(value)
                              FROM config_db_uuid.obj_fq_name_table
                              WHERE key = textAsBlob(?)"#;
-            query(&session, q, &fqname).await?;
+            query(&session, q, &fqname, fmt).await?;
         }
 
         if cmd.uuids {
             let q: &str = r#"SELECT blobastext(key)
                              FROM config_db_uuid.obj_uuid_table"#;
-            query_key(&session, q).await?;
+            query_key(&session, q, fmt).await?;
         }
 
         if cmd.fqnames {
             let q: &str = r#"SELECT blobastext(key)
                              FROM config_db_uuid.obj_fq_name_table"#;
-            query_key(&session, q).await?;
+            query_key(&session, q, fmt).await?;
         }
 
         if cmd.portlist {
             let now = Instant::now();
             simulate_portlist(&session).await?;
-            println!("Time: {}", now.elapsed().as_secs_f32());
+            note(format!("Time: {}", now.elapsed().as_secs_f32()));
         }
 
         if cmd.cqlsh {
-            cqlsh(&session).await?;
+            cqlsh(&session, fmt).await?;
         }
     }
 
     Ok(())
 }
 
-async fn query(session: &Session, q: &str, arg: &str) -> Result<(), anyhow::Error> {
+async fn query(
+    session: &Session,
+    q: &str,
+    arg: &str,
+    fmt: Option<&OutputFormat>,
+) -> Result<(), anyhow::Error> {
     if let Some(rows) = session.query(q, (arg,)).await?.rows {
+        let mut props = Vec::new();
         for row in rows.into_typed::<(String, String, i64)>() {
             let (prop, value, timestamp) = row?;
+            let value = serde_json::from_str::<Value>(&value)?;
+            if fmt.is_some() {
+                props.push(json!({"property": prop, "value": value, "timestamp": timestamp}));
+                continue;
+            }
             println!(
                 "{} | {} | {}",
                 prop,
-                serde_json::from_str::<Value>(&value)?,
+                value,
                 timestamp
             );
         }
+        if let Some(fmt) = fmt {
+            print_output(&json!(props), fmt, None);
+        }
     }
     Ok(())
 }
 
-async fn query_key(session: &Session, q: &str) -> Result<(), anyhow::Error> {
+async fn query_key(
+    session: &Session,
+    q: &str,
+    fmt: Option<&OutputFormat>,
+) -> Result<(), anyhow::Error> {
     if let Some(rows) = session.query(q, &[]).await?.rows {
         let mut uuids: HashSet<String> = HashSet::new();
         for row in rows.into_typed::<(String,)>() {
             let (uuid,) = row?;
             uuids.insert(uuid);
         }
+        if let Some(fmt) = fmt {
+            print_output(&json!(uuids), fmt, None);
+            return Ok(());
+        }
         for u in &uuids {
             println!("{u}");
         }
-        println!("Total: {}", uuids.len());
+        note(format!("Total: {}", uuids.len()));
     }
     Ok(())
 }
@@ -146,7 +178,7 @@ async fn simulate_portlist(session: &Session) -> Result<(), anyhow::Error> {
                           WHERE key = textAsBlob(?)"#;
     let mut uuids: HashSet<String> = HashSet::new();
     // get vmi uuids from fqname
-    println!("*** select virtual_machine_interface from fqnme table:");
+    note("*** select virtual_machine_interface from fqnme table:");
     if let Some(rows) = session
         .query(fqname, ("virtual_machine_interface",))
         .await?
@@ -161,13 +193,13 @@ async fn simulate_portlist(session: &Session) -> Result<(), anyhow::Error> {
     for u in &uuids {
         println!("{u}");
     }
-    println!("Total: {}", uuids.len());
+    note(format!("Total: {}", uuids.len()));
 
     // get timestamp for all uuids
     let timestamp: &str = r#"SELECT blobAsText(column1), value, WRITETIME(value)
                              FROM config_db_uuid.obj_uuid_table
                              WHERE key = textAsBlob(?) AND column1 IN (textAsBlob(?)) ALLOW FILTERING"#;
-    println!("*** select timestamp from uuid table:");
+    note("*** select timestamp from uuid table:");
     for u in &uuids {
         if let Some(rows) = session
             .query(timestamp, (u, "META:latest_col_ts"))
@@ -183,7 +215,7 @@ async fn simulate_portlist(session: &Session) -> Result<(), anyhow::Error> {
 
     // get vn uuids from fqname
     let mut vn_uuids: HashSet<String> = HashSet::new();
-    println!("*** select virtual_network from fqnme table:");
+    note("*** select virtual_network from fqnme table:");
     if let Some(rows) = session.query(fqname, ("virtual_network",)).await?.rows {
         for row in rows.into_typed::<(String, String)>() {
             let (fq, _) = row?;
@@ -195,10 +227,10 @@ async fn simulate_portlist(session: &Session) -> Result<(), anyhow::Error> {
     for u in &vn_uuids {
         println!("{u}");
     }
-    println!("Total: {}", vn_uuids.len());
+    note(format!("Total: {}", vn_uuids.len()));
 
     // get vn from share table
-    println!("*** select project from share table:");
+    note("*** select project from share table:");
     let share: &str = r#"SELECT blobAsText(column1), value
                          FROM config_db_uuid.obj_shared_table
                          WHERE key = textAsBlob(?) AND column1 >= textAsBlob(?) AND column1 <= textAsBlob(?) "#;
@@ -219,7 +251,7 @@ async fn simulate_portlist(session: &Session) -> Result<(), anyhow::Error> {
             println!("{p} | {v}");
         }
     }
-    println!("*** select domain from share table:");
+    note("*** select domain from share table:");
     if let Some(rows) = session
         .query(
             share,
@@ -237,7 +269,7 @@ async fn simulate_portlist(session: &Session) -> Result<(), anyhow::Error> {
             println!("{p} | {v}");
         }
     }
-    println!("*** select global from share table:");
+    note("*** select global from share table:");
     if let Some(rows) = session
         .query(share, ("virtual_network", "global::", "global:;"))
         .await?
@@ -250,7 +282,7 @@ async fn simulate_portlist(session: &Session) -> Result<(), anyhow::Error> {
     }
 
     // get instance ip for all uuids
-    println!("*** select instance ip from uuid table:");
+    note("*** select instance ip from uuid table:");
     let backref: &str = r#"SELECT blobAsText(column1), value, WRITETIME(value)
                           FROM config_db_uuid.obj_uuid_table
                           WHERE key = textAsBlob(?) AND column1 >= textAsBlob(?) AND column1 <= textAsBlob(?)"#;
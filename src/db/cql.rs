@@ -3,7 +3,10 @@ use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::{CompletionType, Config, Context, Editor};
 use rustyline_derive::{Helper, Highlighter, Hinter, Validator};
+use crate::rest::output::{print_output, OutputFormat};
+use scylla::frame::response::result::CqlValue;
 use scylla::{QueryResult, Session};
+use serde_json::{json, Map, Value};
 
 #[derive(Helper, Highlighter, Validator, Hinter)]
 struct CqlHelper;
@@ -171,12 +174,49 @@ impl Completer for CqlHelper {
     }
 }
 
-fn print_result(result: &QueryResult) {
+/// Cell as JSON, text and numbers as they are, blob in hex
+fn cell_value(column: &Option<CqlValue>) -> Value {
+    match column {
+        None => Value::Null,
+        Some(c) => {
+            if let Some(t) = c.as_text() {
+                json!(t)
+            } else if let Some(b) = c.as_blob() {
+                json!(b.iter().map(|i| format!("{:02x}", i)).collect::<String>())
+            } else if let Some(b) = c.as_bigint() {
+                json!(b)
+            } else {
+                json!(format!("{:?}", c))
+            }
+        }
+    }
+}
+
+fn print_result(result: &QueryResult, fmt: Option<&OutputFormat>) {
     if result.rows.is_none() {
         println!("OK");
         return;
     }
 
+    if let Some(fmt) = fmt {
+        let rows: Vec<Value> = result
+            .rows
+            .iter()
+            .flatten()
+            .map(|row| {
+                let obj: Map<String, Value> = result
+                    .col_specs
+                    .iter()
+                    .zip(&row.columns)
+                    .map(|(spec, column)| (spec.name.clone(), cell_value(column)))
+                    .collect();
+                Value::Object(obj)
+            })
+            .collect();
+        print_output(&json!(rows), fmt, None);
+        return;
+    }
+
     for row in result.rows.as_ref().unwrap() {
         for column in &row.columns {
             print!(" | ");
@@ -196,7 +236,7 @@ fn print_result(result: &QueryResult) {
     }
 }
 
-pub async fn cqlsh(session: &Session) -> Result<()> {
+pub async fn cqlsh(session: &Session, fmt: Option<&OutputFormat>) -> Result<()> {
     let config = Config::builder()
         .history_ignore_space(true)
         .completion_type(CompletionType::List)
@@ -214,7 +254,7 @@ pub async fn cqlsh(session: &Session) -> Result<()> {
                 let maybe_res = session.query(line, &[]).await;
                 match maybe_res {
                     Err(err) => println!("Error: {}", err),
-                    Ok(res) => print_result(&res),
+                    Ok(res) => print_result(&res, fmt),
                 }
             }
             Err(ReadlineError::Interrupted) => continue,
@@ -224,3 +264,16 @@ pub async fn cqlsh(session: &Session) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_value() {
+        let blob = Some(CqlValue::Blob(vec![0x00, 0x0f, 0xab]));
+        assert_eq!(cell_value(&blob), json!("000fab"));
+        assert_eq!(cell_value(&Some(CqlValue::BigInt(7))), json!(7));
+        assert_eq!(cell_value(&None), Value::Null);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod cli;
+#[allow(clippy::module_inception)]
 pub mod inspect;
 
 pub use inspect::format_xml;
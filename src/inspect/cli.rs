@@ -1,4 +1,6 @@
 use super::inspect::Introspect;
+use crate::config::read_config;
+use crate::rest::output::OutputFormat;
 use clap::{
     ArgMatches,
     Args,
@@ -10,9 +12,8 @@ use clap::{
 
 #[derive(Args)]
 struct Opts {
-    /// IP address of host
-    #[arg(required = true)]
-    ip: String,
+    /// IP address of host, `[inspect]` or API host in config.toml by default
+    ip: Option<String>,
 
     /// Introspect port number
     #[arg(short, long)]
@@ -196,10 +197,21 @@ pub async fn handle_cli(matches: &ArgMatches) -> Result<(), anyhow::Error> {
             Some(port) => port,
             None => cmd.service.to_port(),
         };
-        let ist = Introspect::new(&cmd.ip, port);
+        let ip = match &cmd.ip {
+            Some(ip) => ip.clone(),
+            None => {
+                let cfg = read_config()?;
+                match &cfg.inspect {
+                    Some(inspect) => inspect.host.clone(),
+                    None => cfg.api_host().to_string(),
+                }
+            }
+        };
+        let fmt = matches.get_one::<OutputFormat>("output").cloned();
+        let ist = Introspect::new(&ip, port).format(fmt);
         let common_cmd = cmd.service.get_common();
-        if common_cmd.is_some() {
-            match common_cmd.unwrap() {
+        if let Some(common_cmd) = common_cmd {
+            match common_cmd {
                 Common::Trace { buffer } => {
                     ist.get_trace(buffer).await?;
                 }
@@ -226,7 +238,7 @@ pub async fn handle_cli(matches: &ArgMatches) -> Result<(), anyhow::Error> {
                 Service::Vrouter(vr_cmd) => {
                     if let Some(key) = vr_cmd.keys {
                         if key {
-                            ist.get(&format!("Snh_Inet4FlowTreeReq")).await?;
+                            ist.get("Snh_Inet4FlowTreeReq").await?;
                         }
                     } else if let Some(entry) = vr_cmd.entry {
                         ist.get(&format!("Snh_FlowsPerInetRouteFlowMgmtKeyReq?x={entry}")).await?;
@@ -1,3 +1,4 @@
+use crate::rest::output::{print_output, OutputFormat};
 use chrono::DateTime;
 use colored::Colorize;
 use log::{debug, info};
@@ -5,12 +6,14 @@ use quick_xml::escape::unescape;
 use quick_xml::events::Event;
 use quick_xml::reader::Reader;
 use reqwest::{self, Client};
+use serde_json::{json, Map, Value};
 use std::ops::Deref;
 use url::Url;
 
 pub struct Introspect {
     http: Client,
     root: String,
+    fmt: Option<OutputFormat>,
 }
 
 impl Introspect {
@@ -18,15 +21,22 @@ impl Introspect {
         Self {
             http: Client::new(),
             root: format!("http://{}:{}/", ip, port),
+            fmt: None,
         }
     }
 
+    /// Print responses in format instead of XML
+    pub fn format(mut self, fmt: Option<OutputFormat>) -> Self {
+        self.fmt = fmt;
+        self
+    }
+
     pub async fn get(self, url: &str) -> anyhow::Result<()> {
         let uri = Url::parse(&self.root)?.join(url)?;
         info!("Request: {}", uri.as_str());
         let response = self.http.get(uri.as_str()).send().await?.text().await?;
         info!("Response: {response}");
-        xml_parser(&response, url)
+        xml_parser(&response, url, self.fmt.as_ref())
     }
 
     pub async fn set_logging(self, level: &str) -> anyhow::Result<()> {
@@ -60,7 +70,7 @@ impl Introspect {
     }
 }
 
-fn xml_parser(xml: &str, url: &str) -> anyhow::Result<()> {
+fn xml_parser(xml: &str, url: &str, fmt: Option<&OutputFormat>) -> anyhow::Result<()> {
     let mut reader = Reader::from_str(xml);
     let mut txt = Vec::new();
     let mut is_sandesh = false;
@@ -74,7 +84,13 @@ fn xml_parser(xml: &str, url: &str) -> anyhow::Result<()> {
         x if x.ends_with(".xml") => is_sandesh = true,
         "/" => is_root = true,
         x if x.starts_with("Snh_") => {
-            return format_xml(xml, false);
+            return match fmt {
+                Some(fmt) => {
+                    print_output(&records(xml_to_json(xml)?), fmt, None);
+                    Ok(())
+                }
+                None => format_xml(xml, false),
+            };
         }
         _ => unreachable!(),
     }
@@ -148,12 +164,131 @@ fn xml_parser(xml: &str, url: &str) -> anyhow::Result<()> {
             _ => (),
         }
     }
+    if let Some(fmt) = fmt {
+        print_output(&json!(txt), fmt, None);
+        return Ok(());
+    }
     for t in txt {
         println!("{t}");
     }
     Ok(())
 }
 
+/// Element of sandesh response, `kind` is its `type` attribute
+struct Node {
+    name: String,
+    kind: String,
+    children: Vec<(String, Value)>,
+    text: String,
+}
+
+impl Node {
+    fn value(self) -> Value {
+        match self.kind.as_str() {
+            // <itf_list type="list"><list type="struct">items</list></itf_list>
+            "list" => Value::Array(
+                self.children
+                    .into_iter()
+                    .flat_map(|(_, v)| match v {
+                        Value::Array(items) => items,
+                        // Items with same name are merged into an array
+                        Value::Object(obj) => obj
+                            .into_iter()
+                            .flat_map(|(_, v)| match v {
+                                Value::Array(items) => items,
+                                v => vec![v],
+                            })
+                            .collect(),
+                        v => vec![v],
+                    })
+                    .collect(),
+            ),
+            "slist" => Value::Array(self.children.into_iter().map(|(_, v)| v).collect()),
+            _ if !self.children.is_empty() => {
+                let mut obj = Map::new();
+                for (name, v) in self.children {
+                    match obj.get_mut(&name) {
+                        Some(Value::Array(items)) => items.push(v),
+                        Some(prev) => *prev = json!([prev.take(), v]),
+                        None => {
+                            obj.insert(name, v);
+                        }
+                    }
+                }
+                Value::Object(obj)
+            }
+            "bool" => json!(self.text == "true"),
+            kind if kind.starts_with('i') || kind.starts_with('u') => self
+                .text
+                .parse::<i64>()
+                .map_or(json!(self.text), |n| json!(n)),
+            _ => json!(self.text),
+        }
+    }
+}
+
+/// Convert sandesh response to JSON, `type` attributes decide lists and scalars
+pub fn xml_to_json(xml: &str) -> anyhow::Result<Value> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    let node = |e: &quick_xml::events::BytesStart| -> anyhow::Result<Node> {
+        let kind = e
+            .try_get_attribute("type")?
+            .map(|a| a.unescape_value().map(|v| v.into_owned()))
+            .transpose()?
+            .unwrap_or_default();
+        Ok(Node {
+            name: std::str::from_utf8(e.name().into_inner())?.to_string(),
+            kind,
+            children: Vec::new(),
+            text: String::new(),
+        })
+    };
+    let mut stack = vec![Node {
+        name: String::new(),
+        kind: String::new(),
+        children: Vec::new(),
+        text: String::new(),
+    }];
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => stack.push(node(&e)?),
+            Event::Empty(e) => {
+                let child = node(&e)?;
+                let parent = stack.last_mut().unwrap();
+                parent.children.push((child.name.clone(), child.value()));
+            }
+            Event::Text(e) => stack.last_mut().unwrap().text += &e.unescape()?,
+            Event::End(_) if stack.len() > 1 => {
+                let child = stack.pop().unwrap();
+                let parent = stack.last_mut().unwrap();
+                parent.children.push((child.name.clone(), child.value()));
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(stack.pop().unwrap().value())
+}
+
+/// Items of a response, which is the only list inside, or the response itself
+pub fn records(mut value: Value) -> Value {
+    loop {
+        value = match value {
+            Value::Object(obj) if obj.len() == 1 => obj.into_iter().next().unwrap().1,
+            Value::Array(mut items) if items.len() == 1 => items.pop().unwrap(),
+            Value::Object(obj) => {
+                let mut lists = obj.values().filter(|v| v.is_array());
+                return match (lists.next(), lists.next()) {
+                    (Some(list), None) => list.clone(),
+                    _ => Value::Object(obj),
+                };
+            }
+            value => return value,
+        }
+    }
+}
+
 pub fn format_xml(xml: &str, include_attr: bool) -> anyhow::Result<()> {
     let mut indent = 0;
     let mut reader = Reader::from_str(xml);
@@ -215,15 +350,11 @@ pub fn format_xml(xml: &str, include_attr: bool) -> anyhow::Result<()> {
                     println!("{}</{}>", " ".repeat(indent), end);
                 }
             }
-            Ok(Event::PI(e)) => {
-                if include_attr {
-                    println!("{}", reader.decoder().decode(e.deref()).unwrap());
-                }
+            Ok(Event::PI(e)) if include_attr => {
+                println!("{}", reader.decoder().decode(e.deref()).unwrap());
             }
-            Ok(Event::CData(e)) => {
-                if include_attr {
-                    println!("{}", reader.decoder().decode(e.deref()).unwrap());
-                }
+            Ok(Event::CData(e)) if include_attr => {
+                println!("{}", reader.decoder().decode(e.deref()).unwrap());
             }
             Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
             Ok(Event::Eof) => break,
@@ -232,3 +363,26 @@ pub fn format_xml(xml: &str, include_attr: bool) -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml_to_json() {
+        let xml = r#"<?xml-stylesheet type="text/xsl" href="/universal_parse.xsl"?>
+<ItfResp type="sandesh"><itf_list type="list" identifier="1"><list type="struct" size="2">
+<ItfSandeshData><index type="i32">0</index><name type="string">eth0</name><active type="bool">true</active></ItfSandeshData>
+<ItfSandeshData><index type="i32">1</index><name type="string">tap&amp;1</name><active type="bool">false</active></ItfSandeshData>
+</list></itf_list><more type="bool">false</more></ItfResp>"#;
+        let value = xml_to_json(xml).unwrap();
+        assert_eq!(value["ItfResp"]["more"], json!(false));
+        assert_eq!(
+            records(value),
+            json!([
+                {"index": 0, "name": "eth0", "active": true},
+                {"index": 1, "name": "tap&1", "active": false},
+            ])
+        );
+    }
+}
@@ -2,40 +2,117 @@ mod cli;
 mod config;
 mod db;
 mod inspect;
+mod manifest;
 mod rest;
+mod stress;
 
 use chrono::DateTime;
 use clap::{ArgMatches, FromArgMatches as _};
-use cli::{Operations, Opts, OutputFormat, Method, BUILDIN_CMD};
+use clap_complete::CompleteEnv;
+use cli::{CleanupOpts, LbOperations, Operations, Opts, OutputFormat, Method, TopologyOpts, BUILDIN_CMD};
 use inspect::format_xml;
+use crate::rest::cascade;
+use crate::rest::filter;
+use crate::rest::path;
+use crate::rest::ledger::{cleanup, Ledger};
+use crate::rest::loadbalance;
+use crate::rest::metrics;
+use crate::rest::output::{self, field_roots, note, print_value};
 use crate::rest::resource::ResourceBuilder;
 use crate::rest::rest::get_token;
 use crate::rest::rest::reschedule_vgws;
 use crate::rest::rest::Output;
-use crate::rest::rest::Rest;
+use crate::rest::rest::{HttpError, Resolve, Rest};
+use crate::rest::topology;
+use crate::rest::watch;
+use reqwest::StatusCode;
 use serde_json::{json, Value};
 use uuid::Uuid;
 use flate2::read::ZlibDecoder;
 use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     env_logger::init();
+    config::set_profile(cli::global_from_args("profile"));
+    config::set_config(cli::global_from_args("config").map(PathBuf::from));
     let cli = cli::build_cli()?;
     let cli = db::cli::build_cli(cli);
     let cli = inspect::cli::build_cli(cli);
+    let cli = stress::cli::build_cli(cli);
+    let cli = config::cli::build_cli(cli);
+    let cli = manifest::cli::build_cli(cli);
+
+    // Completion script by `COMPLETE=bash sdncli`, which calls back here while completing
+    let factory = cli.clone();
+    CompleteEnv::with_factory(move || factory.clone()).complete();
 
     let matches = cli.get_matches();
     let opt = cli::cli_matches(&matches);
-    if handle_cli(&opt).await? {
+    output::set_quiet(opt.quiet);
+    output::set_wide(opt.wide);
+    config::set_context(config::Context {
+        tenant_id: opt.tenant.clone(),
+        user_id: opt.user.clone(),
+        is_admin: opt.no_admin.then_some(false),
+    });
+    let result = dispatch(&matches, &opt).await;
+    if let Some(file) = &opt.metrics {
+        metrics::export(file)?;
+    }
+    if let Err(err) = result {
+        let code = exit_code(&err);
+        if opt.quiet {
+            let mut error = json!({"error": err.to_string(), "exit_code": code});
+            if let Some(http) = err.chain().find_map(|e| e.downcast_ref::<HttpError>()) {
+                error["http"] = http.to_json();
+            }
+            eprintln!("{error}");
+        } else {
+            eprintln!("Error: {err:?}");
+        }
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// Exit code of a failure, so that scripts can tell them apart:
+/// 1 other error, 2 usage (by clap), 3 rejected by API, 4 not found,
+/// 5 API server error, 6 API unreachable
+fn exit_code(err: &anyhow::Error) -> i32 {
+    for cause in err.chain() {
+        if let Some(http) = cause.downcast_ref::<HttpError>() {
+            return match http.status {
+                StatusCode::NOT_FOUND => 4,
+                status if status.is_client_error() => 3,
+                _ => 5,
+            };
+        }
+        if cause.downcast_ref::<reqwest::Error>().is_some() {
+            return 6;
+        }
+    }
+    1
+}
+
+async fn dispatch(matches: &ArgMatches, opt: &Opts) -> Result<(), anyhow::Error> {
+    if config::cli::handle_cli(matches).await? {
         return Ok(());
     }
-    if handle_buildin(&matches).await? {
+    if handle_cli(opt).await? {
         return Ok(());
     }
-    handle_rest(&matches, &opt).await?;
-    db::cli::handle_cli(&matches).await?;
-    inspect::cli::handle_cli(&matches).await?;
+    if handle_buildin(matches, opt).await? {
+        return Ok(());
+    }
+    handle_rest(matches, opt).await?;
+    db::cli::handle_cli(matches).await?;
+    inspect::cli::handle_cli(matches).await?;
+    stress::cli::handle_cli(matches).await?;
+    manifest::cli::handle_cli(matches).await?;
 
     Ok(())
 }
@@ -43,15 +120,15 @@ async fn main() -> Result<(), anyhow::Error> {
 async fn handle_cli(opt: &Opts) -> Result<bool, anyhow::Error> {
     let cfg = config::read_config()?;
     let mut api = Rest::new(&cfg);
-    let output_format = opt.output;
+    let output_format = opt.output.clone();
 
     // Request by JSON file
     if let Some(file) = &opt.file {
-        let oformat = output_format.unwrap_or(OutputFormat::Table).to_string();
+        let oformat = output_format.clone().unwrap_or(OutputFormat::Table);
         let json_config = config::read_json_file(file)?;
 
-        if json_config.port.is_some() {
-            api.set_rest_port(json_config.port.unwrap());
+        if let Some(port) = json_config.port {
+            api.set_rest_port(port);
         }
         if json_config.method == "get" {
             api.get(&json_config.uri)
@@ -64,7 +141,7 @@ async fn handle_cli(opt: &Opts) -> Result<bool, anyhow::Error> {
                 .output(&oformat, None)
                 .await?;
         }
-        println!("API IP: {}", api.host);
+        note(format!("API IP: {}", api.host));
         return Ok(true);
     }
 
@@ -74,7 +151,7 @@ async fn handle_cli(opt: &Opts) -> Result<bool, anyhow::Error> {
             api.set_rest_port(port);
         }
 
-        let oformat = output_format.unwrap_or(OutputFormat::Json).to_string();
+        let oformat = output_format.clone().unwrap_or(OutputFormat::Json);
         match opt.method {
             Some(Method::Post) => if let Some(data) = &opt.data {
                 api.post(uri, data.clone()).await?.output(&oformat, None).await?;
@@ -89,7 +166,7 @@ async fn handle_cli(opt: &Opts) -> Result<bool, anyhow::Error> {
             Some(Method::Delete) => api.delete(uri).await?.output(&oformat, None).await?,
             _ => api.get(uri).await?.output(&oformat, None).await?,
         }
-        println!("API IP: {}", api.host);
+        note(format!("API IP: {}", api.host));
         return Ok(true);
     }
 
@@ -139,27 +216,39 @@ async fn handle_cli(opt: &Opts) -> Result<bool, anyhow::Error> {
 
     // Get API cache
     if opt.cache {
-        let oformat = output_format.unwrap_or(OutputFormat::Json).to_string();
+        let oformat = output_format.clone().unwrap_or(OutputFormat::Json);
         api.post("/obj-cache", json!({"count": 999999}))
             .await?
             .output(&oformat, None)
             .await?;
-        println!("API IP: {}", api.host);
+        note(format!("API IP: {}", api.host));
         return Ok(true);
     }
 
     Ok(false)
 }
 
-async fn handle_buildin(matches: &ArgMatches) -> Result<bool, anyhow::Error> {
+async fn handle_buildin(matches: &ArgMatches, opt: &Opts) -> Result<bool, anyhow::Error> {
     let mut handled = false;
     let cfg = config::read_config()?;
     for cmd in BUILDIN_CMD {
-        if let Some(_matches) = matches.subcommand_matches(&cmd) {
+        if let Some(matches) = matches.subcommand_matches(cmd) {
             handled = true;
             match cmd {
                 "token" => println!("{}", get_token(&cfg.auth).await?),
                 "vgws" => reschedule_vgws(&cfg).await?,
+                "cleanup" => {
+                    let opts = CleanupOpts::from_arg_matches(matches)?;
+                    cleanup(&cfg, opts.dry_run, opts.yes).await?
+                }
+                "loadbalance" => {
+                    let oper = LbOperations::from_arg_matches(matches)?;
+                    loadbalance::handle(&cfg, oper, opt.output.as_ref()).await?
+                }
+                "topology" => {
+                    let opts = TopologyOpts::from_arg_matches(matches)?;
+                    topology::handle(&cfg, opts).await?
+                }
                 _ => unreachable!(),
             }
         }
@@ -167,13 +256,26 @@ async fn handle_buildin(matches: &ArgMatches) -> Result<bool, anyhow::Error> {
     Ok(handled)
 }
 
+/// IDs of names which are not IDs already
+async fn resolve_ids(
+    api: &mut Rest,
+    uri: &str,
+    names: &[String],
+    resolve: Resolve,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let resolved = api.resolve_names(uri, names, resolve).await?;
+    Ok(resolved.into_iter().map(|(_, id)| id).collect())
+}
+
 async fn handle_rest(matches: &ArgMatches, opt: &Opts) -> Result<(), anyhow::Error> {
     let cfg = config::read_config()?;
     let mut api = Rest::new(&cfg);
-    let oformat = opt.output.unwrap_or(OutputFormat::Table).to_string();
+    let oformat = opt.output.clone().unwrap_or(OutputFormat::Table);
     let mut builder = ResourceBuilder::new();
+    let ledger = Ledger::new()?;
+    let parallel = opt.parallel.map_or(cfg.api.parallel, usize::from).max(1);
 
-    for res in cfg.resource {
+    for res in cfg.resource.clone() {
         if let Some(matches) = matches.subcommand_matches(res.cmd.as_str()) {
             let uri: String;
             if res.resource == "member" {
@@ -184,7 +286,7 @@ async fn handle_rest(matches: &ArgMatches, opt: &Opts) -> Result<(), anyhow::Err
                 };
                 uri = format!("/neutron/pool/{pool_id}/member");
             } else {
-                uri = res.uri;
+                uri = res.uri.clone();
             }
             let opers = Operations::from_arg_matches(matches)
                 .map_err(|err| err.exit())
@@ -192,56 +294,241 @@ async fn handle_rest(matches: &ArgMatches, opt: &Opts) -> Result<(), anyhow::Err
             let oper = opers.oper();
             builder.res_type(&res.resource).oper(&oper);
 
+            let (page_size, conds, sort_by, reverse) = match &opers {
+                Operations::List {
+                    all,
+                    page_size,
+                    wheres,
+                    sort_by,
+                    reverse,
+                    ..
+                } => (all.then_some(*page_size), wheres.clone(), sort_by.clone(), *reverse),
+                _ => (None, Vec::new(), None, false),
+            };
+            let is_list = matches!(opers, Operations::List { .. });
+            let resolve = opers.resolve();
+            let (cascade, yes) = match &opers {
+                Operations::Delete { cascade, yes, .. } => (*cascade, *yes),
+                _ => (false, false),
+            };
+            let (watch, until) = match &opers {
+                Operations::Show { watch, until, .. } | Operations::List { watch, until, .. } => {
+                    // `--until` alone watches in default interval
+                    let watch = watch.or((!until.is_empty()).then_some(2));
+                    (watch, until.clone())
+                }
+                _ => (None, Vec::new()),
+            };
             let (names, attr, field, filter) = match opers {
                 Operations::Create { name, attr } => (Some(vec![name]), attr, None, None),
-                Operations::Update { names, attr } => (Some(names), Some(attr), None, None),
-                Operations::Delete { names } => (Some(names), None, None, None),
-                Operations::Show { names, field } => (Some(names), None, field, None),
-                Operations::List { filter, field } => (None, None, field, filter),
+                Operations::Update { names, attr, .. } => (Some(names), Some(attr), None, None),
+                Operations::Delete { names, .. } => (Some(names), None, None, None),
+                Operations::Show { names, field, .. } => (Some(names), None, field, None),
+                Operations::List { filter, field, .. } => (None, None, field, filter),
                 Operations::Oper {
                     name, attr, field, ..
                 } => (Some(vec![name]), attr, field, None),
             };
 
-            if let Some(filters) = filter {
+            // Configured columns for a list in table, unless all of them are asked for
+            let field = match field {
+                None if is_list && !opt.wide && oformat == OutputFormat::Table => {
+                    (!res.columns.is_empty()).then(|| res.columns.clone())
+                }
+                field => field,
+            };
+
+            let filter = filter::server_filters(&conds, filter);
+            if let Some(filters) = &filter {
                 builder.filters(filters.clone());
             };
 
             if let Some(fields) = &field {
-                builder.fields(fields.to_vec());
+                // Fields to check `--where` and `--until`, and ID as marker of next page
+                let mut fields = field_roots(fields);
+                let mut extra = filter::fields(&conds);
+                extra.extend(filter::fields(&until));
+                extra.extend(sort_by.as_deref().map(|p| path::root(p).to_string()));
+                let id = page_size.map(|_| String::from("id"));
+                for f in extra.into_iter().chain(id) {
+                    if !fields.contains(&f) {
+                        fields.push(f);
+                    }
+                }
+                builder.fields(fields);
             };
 
-            if let Some(attr) = attr {
-                for a in attr {
-                    builder.resource(a.as_object().unwrap().clone());
+            let mut attrs = serde_json::Map::new();
+            for a in attr.into_iter().flatten() {
+                attrs.extend(a.as_object().unwrap().clone());
+            }
+            if oper == "CREATE" || oper == "UPDATE" {
+                res.apply_attr(&mut attrs, oper == "CREATE")?;
+            }
+            builder.resource(attrs);
+
+            if cascade {
+                let ids = resolve_ids(&mut api, &uri, names.as_deref().unwrap_or_default(), resolve).await?;
+                cascade::delete(&cfg, &res.resource, &uri, &ids, yes).await?;
+                continue;
+            }
+
+            if let Some(interval) = watch {
+                // Names are resolved once, then same requests are sent in each poll
+                let mut bodies = Vec::new();
+                for id in resolve_ids(&mut api, &uri, names.as_deref().unwrap_or_default(), resolve).await? {
+                    bodies.push(builder.id(id).build()?);
+                }
+                if names.is_none() {
+                    bodies.push(builder.build()?);
                 }
+                api.set_bench(false);
+                watch::watch(interval, &oformat, field, &until, async || {
+                    let mut items = Vec::new();
+                    for body in &bodies {
+                        match api.post(&uri, body.clone()).await?.json().await? {
+                            Value::Array(array) => items.extend(array),
+                            item => items.push(item),
+                        }
+                    }
+                    items.retain(|item| filter::matches_all(&conds, item));
+                    output::sort(&mut items, sort_by.as_deref(), reverse);
+                    // A single resource of `show` is displayed as it is
+                    Ok(match (names.is_some(), items.len()) {
+                        (true, 1) => items.remove(0),
+                        _ => Value::Array(items),
+                    })
+                })
+                .await?;
+                continue;
             }
 
             // This should be last action since it will send request.
             if let Some(names) = &names {
-                for name in names {
-                    if oper == "CREATE" {
-                        builder.name(name);
-                    } else {
-                        match Uuid::parse_str(name) {
-                            Ok(id) => builder.id(id),
-                            Err(_) => {
-                                let id = api.name_to_id(&uri, name).await?;
-                                builder.name(name).id(id)
+                // Names are resolved by one request, a name matching several resources
+                // makes several requests with `--all`
+                let targets = match oper.as_str() {
+                    "CREATE" => names.iter().map(|name| (name.clone(), None)).collect(),
+                    _ => api
+                        .resolve_names(&uri, names, resolve)
+                        .await?
+                        .into_iter()
+                        .map(|(name, id)| (name, Some(id)))
+                        .collect::<Vec<_>>(),
+                };
+                let mut requests = Vec::new();
+                for (name, id) in targets {
+                    // ID given as it is isn't a name
+                    if id.is_none() || Uuid::parse_str(&name).is_err() {
+                        builder.name(&name);
+                    }
+                    if let Some(id) = id {
+                        builder.id(id);
+                    }
+                    requests.push((name, builder.get_id(), builder.build()?));
+                }
+
+                // Requests are sent at once up to the limit, and responses are printed in order.
+                // A failed one doesn't stop others, which are done and recorded anyway.
+                api.authenticate().await?;
+                let limit = Arc::new(Semaphore::new(parallel));
+                let handles = requests
+                    .into_iter()
+                    .map(|(name, id, body)| {
+                        let (mut api, uri, limit) = (api.clone(), uri.clone(), limit.clone());
+                        let handle = tokio::spawn(async move {
+                            let _permit = limit.acquire_owned().await?;
+                            api.post(&uri, body).await
+                        });
+                        (name, id, handle)
+                    })
+                    .collect::<Vec<_>>();
+                let total = handles.len();
+                let (mut failed, mut first_error) = (0, None);
+                for (name, id, handle) in handles {
+                    let result = async {
+                        let response = handle.await??;
+                        match (oper.as_str(), id) {
+                            ("CREATE", _) => {
+                                let text = response.text().await?;
+                                if let Ok(value) = serde_json::from_str::<Value>(&text) {
+                                    ledger.created_value(&res.resource, &uri, &value);
+                                }
+                                text.output(&oformat, field.clone()).await
+                            }
+                            ("DELETE", Some(id)) => {
+                                ledger.deleted(&res.resource, &uri, id);
+                                response.output(&oformat, field.clone()).await
                             }
-                        };
+                            _ => response.output(&oformat, field.clone()).await,
+                        }
                     }
-                    let body = builder.build()?;
-                    api.post(&uri, body)
-                        .await?
-                        .output(&oformat, field.clone())
+                    .await;
+                    match result {
+                        Ok(()) => (),
+                        Err(e) if total == 1 => return Err(e),
+                        Err(e) => {
+                            eprintln!("Error: {oper} {name} - {e}");
+                            failed += 1;
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                }
+                if names.len() > 1 {
+                    metrics::report();
+                }
+                // Exit code is taken from the first failure
+                if let Some(e) = first_error {
+                    return Err(e.context(format!("{failed} of {total} requests failed")));
+                }
+            } else if let Some(page_size) = page_size.filter(|_| sort_by.is_none() && !reverse) {
+                // Items of each page are printed as soon as they arrive, so JSON is a line per item
+                let page_format = match oformat {
+                    OutputFormat::Json => OutputFormat::Text,
+                    ref fmt => fmt.clone(),
+                };
+                let mut total = 0;
+                api.read_all_pages(&uri, &mut builder, filter, page_size, |items| {
+                    let items: Vec<_> = items
+                        .iter()
+                        .filter(|item| filter::matches_all(&conds, item))
+                        .collect();
+                    if items.is_empty() {
+                        return;
+                    }
+                    print_value(&json!(items), &page_format, field.clone(), total == 0);
+                    total += items.len();
+                })
+                .await?;
+                if oformat.has_total() {
+                    note(format!("Total: {total}"));
+                }
+            } else if !conds.is_empty() || sort_by.is_some() || reverse {
+                // Sorting needs every page before printing
+                let mut value = match page_size {
+                    Some(page_size) => {
+                        let mut all = Vec::new();
+                        api.read_all_pages(&uri, &mut builder, filter, page_size, |items| {
+                            all.extend_from_slice(items)
+                        })
                         .await?;
+                        Value::Array(all)
+                    }
+                    None => {
+                        let body = builder.build()?;
+                        api.post(&uri, body).await?.json().await?
+                    }
+                };
+                if let Some(items) = value.as_array_mut() {
+                    items.retain(|item| filter::matches_all(&conds, item));
+                    output::sort(items, sort_by.as_deref(), reverse);
                 }
+                value.to_string().output(&oformat, field).await?;
             } else {
                 let body = builder.build()?;
                 api.post(&uri, body).await?.output(&oformat, field).await?;
             }
-            println!("API IP: {}", api.host);
+            note(format!("API IP: {}", api.host));
         }
     }
 
@@ -0,0 +1,5 @@
+pub mod apply;
+pub mod cli;
+pub mod export;
+#[allow(clippy::module_inception)]
+pub mod manifest;
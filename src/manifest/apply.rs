@@ -0,0 +1,694 @@
+use super::manifest::{create_order, name_of, reference, resolve, Ids, Manifest, Resources};
+use crate::{
+    config::{self, Config},
+    rest::cascade::confirm,
+    rest::ledger::{delete_rank, Ledger},
+    rest::output::note,
+    rest::resource::ResourceBuilder,
+    rest::rest::{same_id, Rest},
+};
+use anyhow::{anyhow, Error};
+use colored::Colorize;
+use serde_json::{Map, Value};
+use std::{collections::HashMap, path::Path};
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+    /// Add an interface of router, whose subnet is in `subnet_id` of attributes
+    Attach,
+}
+
+/// One request needed to converge live state to manifest
+#[derive(Debug)]
+pub struct Change {
+    pub action: Action,
+    pub res_type: String,
+    pub name: String,
+    pub id: Option<Uuid>,
+    /// Attributes to be sent, references are resolved when sending
+    pub attr: Map<String, Value>,
+    /// Live values of attributes to be updated
+    pub old: Map<String, Value>,
+}
+
+impl Change {
+    fn print(&self) {
+        let id = self.id.map_or(String::new(), |id| format!(" ({id})"));
+        let title = format!("{} {}{}", self.res_type, self.name, id);
+        match self.action {
+            Action::Create => {
+                println!("{}", format!("+ {title}").green());
+                for (k, v) in &self.attr {
+                    println!("{}", format!("    {k}: {v}").green());
+                }
+            }
+            Action::Update => {
+                println!("{}", format!("~ {title}").yellow());
+                for (k, v) in &self.attr {
+                    let old = self.old.get(k).unwrap_or(&Value::Null);
+                    println!("{}", format!("    {k}: {old} -> {v}").yellow());
+                }
+            }
+            Action::Delete => println!("{}", format!("- {title}").red()),
+            Action::Attach => {
+                let subnet = self.attr.get("subnet_id").unwrap_or(&Value::Null);
+                println!("{}", format!("+ {title} interface {subnet}").green());
+            }
+        }
+    }
+}
+
+/// Fields which can't be updated, the resource has to be recreated to change them
+const IMMUTABLE: &[(&str, &[&str])] = &[
+    ("subnet", &["network_id", "cidr", "ip_version"]),
+    ("port", &["network_id"]),
+    ("floatingip", &["floating_network_id"]),
+    ("security_group_rule", &["*"]),
+    ("loadbalancer", &["vip_subnet_id", "vip_address"]),
+    (
+        "listener",
+        &["loadbalancer_id", "protocol", "protocol_port"],
+    ),
+    ("pool", &["loadbalancer_id", "listener_id", "protocol"]),
+    ("member", &["address", "protocol_port", "subnet_id"]),
+    ("healthmonitor", &["pool_id", "type"]),
+];
+
+/// Prefixes of `device_owner` of ports made by server, like DHCP and router interface ports
+const SERVER_OWNERS: &[&str] = &["network:", "neutron:", "Octavia"];
+
+fn is_immutable(res_type: &str, key: &str) -> bool {
+    IMMUTABLE
+        .iter()
+        .any(|(t, keys)| *t == res_type && keys.iter().any(|k| *k == "*" || *k == key))
+}
+
+/// Resources not to be pruned: unnamed ones, ones made by server and ones of other tenants
+fn keep(item: &Value, tenant: &str) -> bool {
+    let owner = item["device_owner"].as_str().unwrap_or_default();
+    item["name"].as_str().unwrap_or_default().is_empty()
+        || SERVER_OWNERS.iter().any(|o| owner.starts_with(o))
+        || !same_id(&item["tenant_id"], tenant)
+}
+
+/// Rules listed in a security group, which are created and deleted one by one
+const RULES: &str = "security_group_rules";
+/// Subnets of interfaces listed in a router, which are added one by one
+pub const INTERFACES: &str = "interfaces";
+
+/// Subnets of interfaces of a live router, found from router interface ports
+fn live_interfaces(router: &Value, ports: &[Value]) -> Vec<Value> {
+    ports
+        .iter()
+        .filter(|p| {
+            p["device_owner"] == "network:router_interface" && p["device_id"] == router["id"]
+        })
+        .flat_map(|p| p["fixed_ips"].as_array().into_iter().flatten())
+        .map(|ip| ip["subnet_id"].clone())
+        .collect()
+}
+
+/// Egress rules made by server for a new security group
+fn is_default_rule(rule: &Map<String, Value>) -> bool {
+    let keys = [
+        "protocol",
+        "port_range_min",
+        "port_range_max",
+        "remote_ip_prefix",
+        "remote_group_id",
+    ];
+    rule.get("direction").and_then(|d| d.as_str()) == Some("egress")
+        && keys.iter().all(|k| rule.get(*k).is_none_or(Value::is_null))
+}
+
+/// Live rule has every field of rule in manifest, whose references are resolved
+fn same_rule(rule: &Value, live: &Value) -> bool {
+    rule.as_object()
+        .into_iter()
+        .flatten()
+        .all(|(k, v)| v == live.get(k).unwrap_or(&Value::Null))
+}
+
+fn rule_change(action: Action, group: &str, id: Option<Uuid>, attr: Map<String, Value>) -> Change {
+    Change {
+        action,
+        res_type: String::from("security_group_rule"),
+        name: group.to_string(),
+        id,
+        attr,
+        old: Map::new(),
+    }
+}
+
+fn id_of(value: &Value) -> Option<Uuid> {
+    value["id"].as_str().and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// IDs of live resources by type and name. Those of `tenant` are taken if it's known,
+/// other tenants' only for a name `tenant` doesn't have, like a shared external network.
+fn live_ids(live: &HashMap<String, Vec<Value>>, tenant: Option<&str>) -> Ids {
+    let mut own = Ids::new();
+    let mut others = Ids::new();
+    for (res_type, items) in live {
+        for item in items {
+            if let (Some(name), Some(id)) = (item["name"].as_str(), item["id"].as_str()) {
+                let ids = match tenant {
+                    Some(t) if !same_id(&item["tenant_id"], t) => &mut others,
+                    _ => &mut own,
+                };
+                ids.entry((res_type.clone(), name.to_string()))
+                    .or_default()
+                    .push(id.to_string());
+            }
+        }
+    }
+    for (key, ids) in others {
+        own.entry(key).or_insert(ids);
+    }
+    own
+}
+
+/// Compare manifest with live resources of each type. Only resources of `tenant` are
+/// matched by name if it's known, and deleted if `prune` is set.
+pub fn plan(
+    resources: &Resources,
+    live: &HashMap<String, Vec<Value>>,
+    tenant: Option<&str>,
+    prune: bool,
+) -> Result<Vec<Change>, Error> {
+    let prune = tenant.filter(|_| prune);
+    let ids = live_ids(live, tenant);
+    let mut changes = Vec::new();
+    let mut stale_rules = Vec::new();
+    for res_type in create_order(resources.keys()) {
+        let items = live.get(res_type).map(|l| &l[..]).unwrap_or_default();
+        for desired in &resources[res_type] {
+            let name = name_of(desired).unwrap_or_default();
+            let mut desired = desired.clone();
+            let rules = match res_type.as_str() {
+                "security_group" => desired.remove(RULES),
+                _ => None,
+            };
+            let interfaces = match res_type.as_str() {
+                "router" => desired.remove(INTERFACES),
+                _ => None,
+            };
+            let mut interfaces: Vec<_> = interfaces
+                .iter()
+                .flat_map(|i| i.as_array().into_iter().flatten())
+                .cloned()
+                .collect();
+            let rules: Vec<_> = rules
+                .iter()
+                .flat_map(|r| r.as_array().into_iter().flatten())
+                .filter_map(|r| r.as_object())
+                .collect();
+            let mut new_rules = Vec::new();
+            let found: Vec<_> = items
+                .iter()
+                .filter(|i| i["name"].as_str() == Some(name))
+                .filter(|i| tenant.is_none_or(|t| same_id(&i["tenant_id"], t)))
+                .collect();
+            let mut change = Change {
+                action: Action::Create,
+                res_type: res_type.clone(),
+                name: name.to_string(),
+                id: None,
+                attr: Map::new(),
+                old: Map::new(),
+            };
+            match found[..] {
+                [] => {
+                    change.attr = desired;
+                    changes.push(change);
+                    new_rules.extend(rules.iter().filter(|r| !is_default_rule(r)));
+                }
+                [current] => {
+                    let ports = live.get("port").map(|l| &l[..]).unwrap_or_default();
+                    let attached = live_interfaces(current, ports);
+                    let mut missing = Vec::new();
+                    for subnet in interfaces {
+                        if !attached.contains(&resolve(&subnet, &ids, false)?) {
+                            missing.push(subnet);
+                        }
+                    }
+                    interfaces = missing;
+                    let live_rules = current[RULES].as_array().map(|r| &r[..]);
+                    let live_rules = live_rules.unwrap_or_default();
+                    let resolved = rules
+                        .iter()
+                        .map(|r| resolve(&Value::Object((*r).clone()), &ids, false))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    for (rule, resolved) in rules.iter().zip(&resolved) {
+                        if !live_rules.iter().any(|l| same_rule(resolved, l)) {
+                            new_rules.push(*rule);
+                        }
+                    }
+                    // Rules are pruned only when the group lists them
+                    if prune.is_some_and(|t| same_id(&current["tenant_id"], t)) && !rules.is_empty()
+                    {
+                        for live_rule in live_rules {
+                            if !resolved.iter().any(|r| same_rule(r, live_rule)) {
+                                stale_rules.push(rule_change(
+                                    Action::Delete,
+                                    name,
+                                    id_of(live_rule),
+                                    Map::new(),
+                                ));
+                            }
+                        }
+                    }
+                    for (k, v) in &desired {
+                        let old = current.get(k).unwrap_or(&Value::Null);
+                        if k != "name" && &resolve(v, &ids, false)? != old {
+                            if is_immutable(res_type, k) {
+                                return Err(anyhow!(
+                                    "`{}` of {} `{}` can't be updated from {} to {}, delete it first",
+                                    k,
+                                    res_type,
+                                    name,
+                                    old,
+                                    v
+                                ));
+                            }
+                            change.attr.insert(k.clone(), v.clone());
+                            change.old.insert(k.clone(), old.clone());
+                        }
+                    }
+                    if !change.attr.is_empty() {
+                        change.action = Action::Update;
+                        change.id = id_of(current);
+                        changes.push(change);
+                    }
+                }
+                _ => return Err(anyhow!("Found multiple {} named `{}`", res_type, name)),
+            }
+            for rule in new_rules {
+                let mut attr = rule.clone();
+                attr.insert(
+                    String::from("security_group_id"),
+                    Value::String(reference("security_group", name)),
+                );
+                changes.push(rule_change(Action::Create, name, None, attr));
+            }
+            // A router to be created has no ID until it's sent
+            let router = found.first().and_then(|r| id_of(r));
+            for subnet in interfaces {
+                let mut attr = Map::new();
+                attr.insert(String::from("subnet_id"), subnet);
+                changes.push(Change {
+                    action: Action::Attach,
+                    res_type: res_type.clone(),
+                    name: name.to_string(),
+                    id: router,
+                    attr,
+                    old: Map::new(),
+                });
+            }
+        }
+    }
+
+    changes.append(&mut stale_rules);
+    if let Some(tenant) = prune {
+        let mut types: Vec<_> = resources.keys().collect();
+        types.sort_by_key(|t| delete_rank(t));
+        for res_type in types {
+            let wanted: Vec<_> = resources[res_type].iter().filter_map(name_of).collect();
+            for item in live.get(res_type).into_iter().flatten() {
+                if keep(item, tenant) {
+                    continue;
+                }
+                let name = item["name"].as_str().unwrap_or_default();
+                if !wanted.contains(&name) {
+                    changes.push(Change {
+                        action: Action::Delete,
+                        res_type: res_type.clone(),
+                        name: name.to_string(),
+                        id: id_of(item),
+                        attr: Map::new(),
+                        old: Map::new(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// READALL resources of a type
+pub async fn read_all(api: &mut Rest, cfg: &Config, res_type: &str) -> Result<Vec<Value>, Error> {
+    let uri = uri(cfg, res_type)?;
+    let body = ResourceBuilder::new()
+        .res_type(res_type)
+        .oper("READALL")
+        .build()?;
+    let value: Value = api.post(&uri, body).await?.json().await?;
+    Ok(value.as_array().cloned().unwrap_or_default())
+}
+
+/// URI of a type, which must not depend on a parent like members of pool
+pub fn uri(cfg: &Config, res_type: &str) -> Result<String, Error> {
+    let uri = config::uri_of(&cfg.resource, res_type);
+    if uri.contains('<') {
+        return Err(anyhow!(
+            "{} is not supported since its URI is {}",
+            res_type,
+            uri
+        ));
+    }
+    Ok(uri)
+}
+
+pub async fn apply(
+    cfg: &Config,
+    file: &Path,
+    dry_run: bool,
+    prune: bool,
+    yes: bool,
+) -> Result<(), Error> {
+    let manifest = Manifest::read(file)?;
+    let mut api = Rest::new(cfg);
+    api.set_bench(false);
+
+    let mut types: Vec<String> = manifest.resources.keys().cloned().collect();
+    types.extend(manifest.referenced_types());
+    // Interfaces of live routers are found from ports
+    let mut routers = manifest.resources.get("router").into_iter().flatten();
+    if routers.any(|r| r.contains_key(INTERFACES)) {
+        types.push(String::from("port"));
+    }
+    types.sort();
+    types.dedup();
+    let mut live = HashMap::new();
+    for res_type in types {
+        let items = read_all(&mut api, cfg, &res_type).await?;
+        live.insert(res_type, items);
+    }
+
+    let tenant = api.tenant_id().await?;
+    if prune && tenant.is_none() {
+        return Err(anyhow!("Tenant is unknown to prune, set it by --tenant"));
+    }
+    let changes = plan(&manifest.resources, &live, tenant.as_deref(), prune)?;
+    changes.iter().for_each(Change::print);
+    let count = |action: Action| changes.iter().filter(|c| c.action == action).count();
+    note(format!(
+        "Plan: {} to create, {} to update, {} to delete, {} interfaces to add",
+        count(Action::Create),
+        count(Action::Update),
+        count(Action::Delete),
+        count(Action::Attach)
+    ));
+    if dry_run || changes.is_empty() {
+        return Ok(());
+    }
+    if count(Action::Delete) > 0 && !yes && !confirm("Apply the plan with deletion?")? {
+        note("Cancelled");
+        return Ok(());
+    }
+
+    let ledger = Ledger::new()?;
+    let mut ids = live_ids(&live, tenant.as_deref());
+    for change in changes {
+        let uri = uri(cfg, &change.res_type)?;
+        let attr = resolve(&Value::Object(change.attr), &ids, true)?;
+        let oper = match change.action {
+            Action::Create => "CREATE",
+            Action::Update => "UPDATE",
+            Action::Delete => "DELETE",
+            Action::Attach => "ADDINTERFACE",
+        };
+        let mut builder = ResourceBuilder::new();
+        builder
+            .res_type(&change.res_type)
+            .oper(oper)
+            .resource(attr.as_object().cloned().unwrap_or_default());
+        let id = match (&change.action, change.id) {
+            // Router created above
+            (Action::Attach, None) => {
+                let router = reference("router", &change.name);
+                let router = resolve(&Value::String(router), &ids, true)?;
+                Uuid::parse_str(router.as_str().unwrap_or_default()).ok()
+            }
+            (_, id) => id,
+        };
+        if let Some(id) = id {
+            builder.id(id);
+        }
+        let text = api.post(&uri, builder.build()?).await?.text().await?;
+        match change.action {
+            Action::Create => {
+                let value: Value = serde_json::from_str(&text).unwrap_or_default();
+                ledger.created_value(&change.res_type, &uri, &value);
+                if let Some(id) = id_of(&value) {
+                    ids.insert(
+                        (change.res_type.clone(), change.name.clone()),
+                        vec![id.to_string()],
+                    );
+                }
+            }
+            Action::Delete => {
+                if let Some(id) = change.id {
+                    ledger.deleted(&change.res_type, &uri, id);
+                }
+            }
+            Action::Update | Action::Attach => (),
+        }
+        note(format!("{oper} {} {} done", change.res_type, change.name));
+    }
+    note(format!("API IP: {}", api.host));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_plan() {
+        let mut resources: Resources = toml::from_str(
+            r#"
+            [[subnet]]
+            name = "s1"
+            network_id = "{network:n1}"
+            cidr = "10.0.1.0/24"
+
+            [[network]]
+            name = "n1"
+
+            [[network]]
+            name = "n2"
+            "router:external" = true
+
+            [[port]]
+            name = "p1"
+            network_id = "{network:n1}"
+            "#,
+        )
+        .unwrap();
+        let n1 = "6bd0768b-0beb-4b30-9916-a3c445fede1c";
+        let t1 = "b00c06f6d02e44939073b566f409b496";
+        let mut live = HashMap::from([
+            (
+                String::from("network"),
+                vec![
+                    json!({"id": n1, "name": "n1", "tenant_id": t1}),
+                    json!({"id": "5c6c4a8e-2e5b-4c0c-9f4b-7b7f0a3c1d2e", "name": "old",
+                           "tenant_id": t1}),
+                    json!({"id": "7d1e2f3a-4b5c-4d6e-8f70-8192a3b4c5d6", "name": "",
+                           "tenant_id": t1}),
+                    json!({"id": "8e2f3a4b-5c6d-4e7f-8091-a2b3c4d5e6f7", "name": "other",
+                           "tenant_id": "e82dedfaad3f46168cd373baf2bdb4ee"}),
+                ],
+            ),
+            (
+                String::from("subnet"),
+                vec![
+                    json!({"id": "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d", "name": "s1",
+                            "network_id": n1, "cidr": "10.0.0.0/24", "tenant_id": t1}),
+                ],
+            ),
+            (
+                String::from("port"),
+                vec![
+                    json!({"id": "1b2c3d4e-5f6a-4b7c-8d9e-0f1a2b3c4d5e", "name": "p1",
+                           "network_id": n1, "tenant_id": t1}),
+                    json!({"id": "2c3d4e5f-6a7b-4c8d-9e0f-1a2b3c4d5e6f", "name": "gw",
+                           "network_id": n1, "tenant_id": t1,
+                           "device_owner": "network:router_gateway"}),
+                ],
+            ),
+        ]);
+
+        // CIDR can't be updated
+        let err = plan(&resources, &live, Some(t1), true).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("`cidr` of subnet `s1` can't be updated"));
+
+        live.get_mut("subnet").unwrap()[0]["cidr"] = json!("10.0.1.0/24");
+        live.get_mut("subnet").unwrap()[0]["description"] = json!("old");
+        resources.get_mut("subnet").unwrap()[0].insert("description".into(), json!("new"));
+        assert_eq!(plan(&resources, &live, None, false).unwrap().len(), 2);
+
+        // Unnamed, server owned and other tenant's resources are not pruned
+        let changes = plan(
+            &resources,
+            &live,
+            Some("b00c06f6-d02e-4493-9073-b566f409b496"),
+            true,
+        )
+        .unwrap();
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (&c.action, c.res_type.as_str(), c.name.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (&Action::Create, "network", "n2"),
+                (&Action::Update, "subnet", "s1"),
+                (&Action::Delete, "network", "old"),
+            ]
+        );
+        assert_eq!(
+            changes[1].attr,
+            *json!({"description": "new"}).as_object().unwrap()
+        );
+    }
+    #[test]
+    fn test_plan_other_tenant() {
+        let resources: Resources = toml::from_str(
+            r#"
+            [[network]]
+            name = "n1"
+            description = "mine"
+            "#,
+        )
+        .unwrap();
+        let t1 = "b00c06f6d02e44939073b566f409b496";
+        let live = HashMap::from([(
+            String::from("network"),
+            vec![
+                json!({"id": "8e2f3a4b-5c6d-4e7f-8091-a2b3c4d5e6f7", "name": "n1",
+                        "tenant_id": "e82dedfaad3f46168cd373baf2bdb4ee"}),
+            ],
+        )]);
+
+        // Same name of another tenant is not updated, but created in own tenant
+        let changes = plan(&resources, &live, Some(t1), false).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].action, Action::Create);
+        assert_eq!(changes[0].id, None);
+
+        // Nor does it make the name ambiguous
+        let mut live = live;
+        live.get_mut("network").unwrap().push(
+            json!({"id": "6bd0768b-0beb-4b30-9916-a3c445fede1c", "name": "n1", "tenant_id": t1}),
+        );
+        let changes = plan(&resources, &live, Some(t1), false).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].action, Action::Update);
+        assert_eq!(
+            changes[0].id,
+            Uuid::parse_str("6bd0768b-0beb-4b30-9916-a3c445fede1c").ok()
+        );
+
+        // References resolve to own resource of the name, others' only if there is none
+        let resources: Resources = toml::from_str(
+            r#"
+            [[subnet]]
+            name = "s1"
+            network_id = "{network:n1}"
+
+            [[port]]
+            name = "p1"
+            network_id = "{network:public}"
+            "#,
+        )
+        .unwrap();
+        let public = "7d1e2f3a-4b5c-4d6e-8f70-8192a3b4c5d6";
+        live.get_mut("network").unwrap().push(
+            json!({"id": public, "name": "public", "tenant_id": "e82dedfaad3f46168cd373baf2bdb4ee"}),
+        );
+        live.insert(
+            String::from("subnet"),
+            vec![
+                json!({"id": "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d", "name": "s1",
+                        "network_id": "6bd0768b-0beb-4b30-9916-a3c445fede1c", "tenant_id": t1}),
+            ],
+        );
+        live.insert(
+            String::from("port"),
+            vec![
+                json!({"id": "1b2c3d4e-5f6a-4b7c-8d9e-0f1a2b3c4d5e", "name": "p1",
+                        "network_id": public, "tenant_id": t1}),
+            ],
+        );
+        assert!(plan(&resources, &live, Some(t1), false).unwrap().is_empty());
+        assert!(plan(&resources, &live, None, false).is_err());
+    }
+
+    #[test]
+    fn test_plan_interfaces() {
+        let resources: Resources = toml::from_str(
+            r#"
+            [[router]]
+            name = "r1"
+            interfaces = ["{subnet:s1}", "{subnet:s2}"]
+            "#,
+        )
+        .unwrap();
+        let (r1, s1, s2) = (
+            "5c6c4a8e-2e5b-4c0c-9f4b-7b7f0a3c1d2e",
+            "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d",
+            "1b2c3d4e-5f6a-4b7c-8d9e-0f1a2b3c4d5e",
+        );
+        let mut live = HashMap::from([
+            (
+                String::from("subnet"),
+                vec![
+                    json!({"id": s1, "name": "s1"}),
+                    json!({"id": s2, "name": "s2"}),
+                ],
+            ),
+            (
+                String::from("port"),
+                vec![
+                    json!({"id": "2c3d4e5f-6a7b-4c8d-9e0f-1a2b3c4d5e6f", "device_id": r1,
+                           "device_owner": "network:router_interface",
+                           "fixed_ips": [{"subnet_id": s1, "ip_address": "10.0.0.1"}]}),
+                ],
+            ),
+        ]);
+
+        // Router to be created has no ID yet
+        let changes = plan(&resources, &live, None, false).unwrap();
+        let summary: Vec<_> = changes.iter().map(|c| (&c.action, c.id)).collect();
+        assert_eq!(
+            summary,
+            [
+                (&Action::Create, None),
+                (&Action::Attach, None),
+                (&Action::Attach, None)
+            ]
+        );
+        assert!(!changes[0].attr.contains_key(INTERFACES));
+
+        // Only the interface missing on live router is added
+        live.insert(
+            String::from("router"),
+            vec![json!({"id": r1, "name": "r1"})],
+        );
+        let changes = plan(&resources, &live, None, false).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].action, Action::Attach);
+        assert_eq!(changes[0].id, Uuid::parse_str(r1).ok());
+        assert_eq!(changes[0].attr["subnet_id"], json!("{subnet:s2}"));
+    }
+}
@@ -0,0 +1,77 @@
+use super::apply::apply;
+use super::export::export;
+use crate::config::read_config;
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+use std::path::PathBuf;
+
+#[derive(Args)]
+struct ApplyOpts {
+    /// Manifest listing resources by type (TOML, YAML or JSON5 by extension)
+    #[arg(short, long)]
+    file: PathBuf,
+
+    /// Only print the difference between manifest and live resources
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Delete resources of the types in manifest which are not listed in it
+    #[arg(long)]
+    prune: bool,
+
+    /// Prune without confirmation
+    #[arg(short, long, requires = "prune", conflicts_with = "dry_run")]
+    yes: bool,
+}
+
+#[derive(Args)]
+struct ExportOpts {
+    /// Manifest to be written (TOML, YAML or JSON by extension), print TOML if not set
+    #[arg(short, long)]
+    file: Option<PathBuf>,
+
+    /// Only export these resource types, example: --type=network,subnet
+    #[arg(short, long = "type", value_delimiter = ',')]
+    types: Vec<String>,
+
+    /// More fields to be dropped, example: --exclude=mac_address,fixed_ips
+    #[arg(short = 'x', long, value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Export resources of every tenant instead of own tenant only
+    #[arg(long)]
+    all_tenants: bool,
+}
+
+pub fn build_cli(cmd: Command) -> Command {
+    let cli = Command::new("apply").about("Create, update or delete resources to match a manifest");
+    let cli = ApplyOpts::augment_args(cli);
+    let export = Command::new("export").about("Export live resources to a manifest for `apply`");
+    let export = ExportOpts::augment_args(export);
+    cmd.subcommand(cli).subcommand(export)
+}
+
+pub async fn handle_cli(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if let Some(matches) = matches.subcommand_matches("apply") {
+        let cmd = ApplyOpts::from_arg_matches(matches)
+            .map_err(|err| err.exit())
+            .unwrap();
+        let cfg = read_config()?;
+        apply(&cfg, &cmd.file, cmd.dry_run, cmd.prune, cmd.yes).await?;
+    }
+    if let Some(matches) = matches.subcommand_matches("export") {
+        let cmd = ExportOpts::from_arg_matches(matches)
+            .map_err(|err| err.exit())
+            .unwrap();
+        let cfg = read_config()?;
+        export(
+            &cfg,
+            &cmd.types,
+            &cmd.exclude,
+            cmd.file.as_deref(),
+            cmd.all_tenants,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,439 @@
+use super::apply::{read_all, INTERFACES};
+use super::manifest::{reference, Manifest, Resources};
+use crate::config::Config;
+use crate::rest::output::note;
+use crate::rest::rest::{same_id, Rest};
+use anyhow::{anyhow, Error};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, path::Path};
+use uuid::Uuid;
+
+/// Fields managed by server, which can't be replayed on another controller
+const SERVER_FIELDS: [&str; 13] = [
+    "id",
+    "fq_name",
+    "tenant_id",
+    "project_id",
+    "created_at",
+    "updated_at",
+    "created",
+    "last_modified",
+    "status",
+    "provisioning_status",
+    "operating_status",
+    "revision_number",
+    "uuid",
+];
+
+/// Fields listing resources referring to this one, which are maintained by server
+const BACK_REFS: &[(&str, &[&str])] = &[
+    ("network", &["subnets"]),
+    ("router", &["ports", "interfaces"]),
+    ("security_group", &["security_group_rules"]),
+    ("loadbalancer", &["listeners", "pools", "vip_port_id"]),
+    ("listener", &["loadbalancers"]),
+    (
+        "pool",
+        &["listeners", "loadbalancers", "members", "healthmonitor_id"],
+    ),
+    ("healthmonitor", &["pools"]),
+    ("firewall_policy", &["firewall_groups"]),
+    ("firewall_rule", &["firewall_policy_id"]),
+];
+
+fn is_back_ref(res_type: &str, key: &str) -> bool {
+    BACK_REFS
+        .iter()
+        .any(|(t, keys)| *t == res_type && keys.contains(&key))
+}
+
+/// Drop server fields and null values, which TOML can't represent
+fn strip(res_type: &str, item: &Map<String, Value>, exclude: &[String]) -> Map<String, Value> {
+    item.iter()
+        .filter(|(k, v)| {
+            !v.is_null()
+                && !SERVER_FIELDS.contains(&k.as_str())
+                && !is_back_ref(res_type, k)
+                && !exclude.contains(k)
+        })
+        .map(|(k, v)| (k.clone(), strip_value(v)))
+        .collect()
+}
+
+fn strip_value(v: &Value) -> Value {
+    match v {
+        Value::Array(a) => {
+            Value::Array(a.iter().filter(|v| !v.is_null()).map(strip_value).collect())
+        }
+        Value::Object(o) => Value::Object(
+            o.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), strip_value(v)))
+                .collect(),
+        ),
+        _ => v.clone(),
+    }
+}
+
+/// Replace IDs of exported resources with `{type:name}`
+fn symbolize(v: &Value, refs: &HashMap<Uuid, String>) -> Value {
+    match v {
+        Value::String(s) => match Uuid::parse_str(s).ok().and_then(|id| refs.get(&id)) {
+            Some(r) => Value::String(r.clone()),
+            None => v.clone(),
+        },
+        Value::Array(a) => Value::Array(a.iter().map(|v| symbolize(v, refs)).collect()),
+        Value::Object(o) => Value::Object(
+            o.iter()
+                .map(|(k, v)| (k.clone(), symbolize(v, refs)))
+                .collect(),
+        ),
+        _ => v.clone(),
+    }
+}
+
+/// Router interface port, which is exported as a subnet in `interfaces` of its router
+fn is_interface(res_type: &str, item: &Map<String, Value>) -> bool {
+    res_type == "port"
+        && item.get("device_owner").and_then(|o| o.as_str()) == Some("network:router_interface")
+}
+
+/// Turn live resources of each type into a portable manifest.
+/// Resources without a name, or with a name taken by another one, are skipped, except
+/// unnamed security group rules, which are listed in `security_group_rules` of their group,
+/// and router interface ports, whose subnets are listed in `interfaces` of their router.
+pub fn portable(live: &HashMap<String, Vec<Value>>, exclude: &[String]) -> Resources {
+    let mut refs = HashMap::new();
+    let mut kept: Vec<(&String, &Map<String, Value>)> = Vec::new();
+    let mut rules: HashMap<&str, Vec<&Map<String, Value>>> = HashMap::new();
+    let mut interfaces: HashMap<&str, Vec<Value>> = HashMap::new();
+    let mut types: Vec<_> = live.keys().collect();
+    types.sort();
+    for res_type in types {
+        let mut names = Vec::new();
+        for item in live[res_type].iter().filter_map(|i| i.as_object()) {
+            let id = item.get("id").and_then(|i| i.as_str()).unwrap_or_default();
+            if is_interface(res_type, item) {
+                let router = item.get("device_id").and_then(|d| d.as_str());
+                let subnets = item.get("fixed_ips").and_then(|f| f.as_array());
+                interfaces
+                    .entry(router.unwrap_or_default())
+                    .or_default()
+                    .extend(
+                        subnets
+                            .into_iter()
+                            .flatten()
+                            .map(|ip| ip["subnet_id"].clone()),
+                    );
+                continue;
+            }
+            let name = match item.get("name").and_then(|n| n.as_str()) {
+                Some(name) if !name.is_empty() => name,
+                _ if res_type == "security_group_rule" => {
+                    let group = item.get("security_group_id").and_then(|g| g.as_str());
+                    rules
+                        .entry(group.unwrap_or_default())
+                        .or_default()
+                        .push(item);
+                    continue;
+                }
+                _ => {
+                    eprintln!("Warning: skip {} {} without name", res_type, id);
+                    continue;
+                }
+            };
+            if names.contains(&name) {
+                eprintln!(
+                    "Warning: skip {} {} since name `{}` is taken",
+                    res_type, id, name
+                );
+                continue;
+            }
+            names.push(name);
+            if let Ok(id) = Uuid::parse_str(id) {
+                refs.insert(id, reference(res_type, name));
+            }
+            kept.push((res_type, item));
+        }
+    }
+
+    let mut resources = Resources::new();
+    for (res_type, item) in kept {
+        let mut stripped = strip(res_type, item, exclude);
+        let id = item.get("id").and_then(|i| i.as_str()).unwrap_or_default();
+        // Only IDs of security groups are found in rules
+        if let Some(rules) = rules.remove(id) {
+            let rules = rules
+                .into_iter()
+                .map(|r| {
+                    let mut r = strip("security_group_rule", r, exclude);
+                    r.remove("security_group_id");
+                    Value::Object(r)
+                })
+                .collect();
+            stripped.insert(String::from("security_group_rules"), Value::Array(rules));
+        }
+        if let Some(subnets) = interfaces.remove(id).filter(|_| res_type == "router") {
+            let subnets = symbolize(&Value::Array(subnets), &refs);
+            for subnet in subnets.as_array().into_iter().flatten() {
+                if !subnet.as_str().is_some_and(|s| s.starts_with('{')) {
+                    eprintln!(
+                        "Warning: interface of router {} on subnet {} which is not exported",
+                        id, subnet
+                    );
+                }
+            }
+            stripped.insert(String::from(INTERFACES), subnets);
+        }
+        let item = symbolize(&Value::Object(stripped), &refs);
+        resources
+            .entry(res_type.clone())
+            .or_default()
+            .push(item.as_object().cloned().unwrap_or_default());
+    }
+    for (router, ports) in interfaces {
+        eprintln!(
+            "Warning: skip {} interfaces of router {} not exported",
+            ports.len(),
+            router
+        );
+    }
+    for (group, rules) in rules {
+        eprintln!(
+            "Warning: skip {} rules without name of security group {} not exported",
+            rules.len(),
+            group
+        );
+    }
+    resources
+}
+
+/// Export resources of own tenant, or of every tenant if `all_tenants` is set
+pub async fn export(
+    cfg: &Config,
+    types: &[String],
+    exclude: &[String],
+    file: Option<&Path>,
+    all_tenants: bool,
+) -> Result<(), Error> {
+    let mut api = Rest::new(cfg);
+    api.set_bench(false);
+    let tenant = match all_tenants {
+        true => None,
+        false => Some(api.tenant_id().await?.ok_or_else(|| {
+            anyhow!("Tenant is unknown to export, set it by --tenant or use --all-tenants")
+        })?),
+    };
+    // All configured types which don't depend on a parent, or the types asked for
+    let types: Vec<String> = if types.is_empty() {
+        let mut all: Vec<_> = cfg
+            .resource
+            .iter()
+            .filter(|r| {
+                let nested = r.uri.contains('<');
+                if nested {
+                    eprintln!("Warning: skip {} since its URI is {}", r.resource, r.uri);
+                }
+                !nested
+            })
+            .map(|r| r.resource.clone())
+            .collect();
+        all.sort();
+        all.dedup();
+        all
+    } else {
+        types.to_vec()
+    };
+    let mut live = HashMap::new();
+    for res_type in types {
+        let items = read_all(&mut api, cfg, &res_type).await?;
+        live.insert(res_type, items);
+    }
+    // Interfaces of routers are found from ports
+    if live.contains_key("router") && !live.contains_key("port") {
+        let ports = read_all(&mut api, cfg, "port").await?;
+        let ports = ports
+            .into_iter()
+            .filter(|p| p.as_object().is_some_and(|p| is_interface("port", p)))
+            .collect();
+        live.insert(String::from("port"), ports);
+    }
+    // READALL as admin returns resources of every tenant
+    if let Some(tenant) = &tenant {
+        for items in live.values_mut() {
+            items.retain(|item| same_id(&item["tenant_id"], tenant));
+        }
+    }
+
+    let manifest = Manifest {
+        resources: portable(&live, exclude),
+    };
+    match file {
+        Some(file) => {
+            manifest.write(file)?;
+            let count: usize = manifest.resources.values().map(|r| r.len()).sum();
+            note(format!("Exported {} resources to {:?}", count, file));
+        }
+        None => print!("{}", manifest.to_string("toml")?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::apply::{plan, Action};
+    use super::super::manifest::{resolve, Ids};
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_portable() {
+        let n1 = "6bd0768b-0beb-4b30-9916-a3c445fede1c";
+        let live = HashMap::from([
+            (
+                String::from("network"),
+                vec![
+                    json!({"id": n1, "name": "n1", "status": "ACTIVE", "fq_name": ["a", "n1"]}),
+                    json!({"id": "5c6c4a8e-2e5b-4c0c-9f4b-7b7f0a3c1d2e", "name": ""}),
+                ],
+            ),
+            (
+                String::from("subnet"),
+                vec![
+                    json!({"id": "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d", "name": "s1",
+                            "network_id": n1, "gateway_ip": null, "cidr": "10.0.0.0/24"}),
+                ],
+            ),
+        ]);
+        let resources = portable(&live, &[String::from("cidr")]);
+        assert_eq!(
+            json!(resources),
+            json!({
+                "network": [{"name": "n1"}],
+                "subnet": [{"name": "s1", "network_id": "{network:n1}"}],
+            })
+        );
+        let toml = Manifest { resources }.to_string("toml").unwrap();
+        assert!(toml.contains("network_id = \"{network:n1}\""));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (n1, s1, r1, p1) = (
+            "6bd0768b-0beb-4b30-9916-a3c445fede1c",
+            "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d",
+            "5c6c4a8e-2e5b-4c0c-9f4b-7b7f0a3c1d2e",
+            "1b2c3d4e-5f6a-4b7c-8d9e-0f1a2b3c4d5e",
+        );
+        let (sg, lb, ls, pool) = (
+            "2c3d4e5f-6a7b-4c8d-9e0f-1a2b3c4d5e6f",
+            "7d1e2f3a-4b5c-4d6e-8f70-8192a3b4c5d6",
+            "8e2f3a4b-5c6d-4e7f-8091-a2b3c4d5e6f7",
+            "9f3a4b5c-6d7e-4f80-91a2-b3c4d5e6f708",
+        );
+        let rules = [
+            json!({"id": "3d4e5f6a-7b8c-4d9e-8f1a-2b3c4d5e6f70", "security_group_id": sg,
+                   "direction": "egress", "ethertype": "IPv4", "protocol": null}),
+            json!({"id": "4e5f6a7b-8c9d-4e0f-9a2b-3c4d5e6f7081", "security_group_id": sg,
+                   "direction": "ingress", "ethertype": "IPv4", "protocol": "tcp",
+                   "port_range_min": 22, "port_range_max": 22, "remote_group_id": sg}),
+        ];
+        let live = HashMap::from([
+            (
+                String::from("network"),
+                vec![json!({"id": n1, "name": "n1", "subnets": [s1]})],
+            ),
+            (
+                String::from("subnet"),
+                vec![json!({"id": s1, "name": "s1", "network_id": n1, "cidr": "10.0.0.0/24"})],
+            ),
+            (
+                String::from("router"),
+                vec![json!({"id": r1, "name": "r1", "ports": [{"id": p1}], "interfaces": [s1]})],
+            ),
+            (
+                String::from("port"),
+                vec![
+                    json!({"id": p1, "name": "", "network_id": n1, "device_id": r1,
+                           "device_owner": "network:router_interface",
+                           "fixed_ips": [{"subnet_id": s1, "ip_address": "10.0.0.1"}]}),
+                ],
+            ),
+            (
+                String::from("security_group"),
+                vec![json!({"id": sg, "name": "web", "security_group_rules": rules})],
+            ),
+            (String::from("security_group_rule"), rules.to_vec()),
+            (
+                String::from("loadbalancer"),
+                vec![json!({"id": lb, "name": "lb", "vip_subnet_id": s1,
+                            "listeners": [{"id": ls}], "pools": [{"id": pool}]})],
+            ),
+            (
+                String::from("listener"),
+                vec![json!({"id": ls, "name": "lb-listener", "protocol": "HTTP",
+                            "protocol_port": 80, "loadbalancer_id": lb,
+                            "loadbalancers": [{"id": lb}]})],
+            ),
+            (
+                String::from("pool"),
+                vec![json!({"id": pool, "name": "lb-pool", "protocol": "HTTP",
+                            "listener_id": ls, "listeners": [{"id": ls}], "members": []})],
+            ),
+        ]);
+        let toml = Manifest {
+            resources: portable(&live, &[]),
+        }
+        .to_string("toml")
+        .unwrap();
+        let manifest = Manifest::parse(&toml, "toml").unwrap();
+        assert_eq!(
+            json!(manifest.resources["security_group"]),
+            json!([{"name": "web", "security_group_rules": [
+                {"direction": "egress", "ethertype": "IPv4"},
+                {"direction": "ingress", "ethertype": "IPv4", "protocol": "tcp",
+                 "port_range_min": 22, "port_range_max": 22,
+                 "remote_group_id": "{security_group:web}"},
+            ]}])
+        );
+
+        assert_eq!(
+            json!(manifest.resources["router"]),
+            json!([{"name": "r1", "interfaces": ["{subnet:s1}"]}])
+        );
+        assert!(!manifest.resources.contains_key("port"));
+
+        // Everything is created with references resolved in order, default rule is skipped
+        let changes = plan(&manifest.resources, &HashMap::new(), None, false).unwrap();
+        let mut ids = Ids::new();
+        for change in &changes {
+            let action = match change.res_type.as_str() {
+                "router" if change.attr.contains_key("subnet_id") => Action::Attach,
+                _ => Action::Create,
+            };
+            assert_eq!(change.action, action);
+            assert!(change
+                .attr
+                .keys()
+                .all(|k| !is_back_ref(&change.res_type, k)));
+            resolve(&json!(change.attr), &ids, true).unwrap();
+            let key = (change.res_type.clone(), change.name.clone());
+            ids.insert(key, vec![Uuid::new_v4().to_string()]);
+        }
+        let types: Vec<_> = changes.iter().map(|c| c.res_type.as_str()).collect();
+        assert_eq!(
+            types,
+            [
+                "security_group",
+                "security_group_rule",
+                "network",
+                "subnet",
+                "router",
+                "router",
+                "loadbalancer",
+                "listener",
+                "pool",
+            ]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Manifest lists resources by type, each one is identified by its name.
+//! Another resource is referenced by `{type:name}`, which is replaced by its ID:
+//!
+//! ```toml
+//! [[network]]
+//! name = "web"
+//!
+//! [[subnet]]
+//! name = "web-v4"
+//! network_id = "{network:web}"
+//! cidr = "10.0.0.0/24"
+//! ip_version = 4
+//!
+//! [[security_group]]
+//! name = "web"
+//! security_group_rules = [
+//!     { direction="ingress", ethertype="IPv4", protocol="tcp", port_range_min=80, port_range_max=80 },
+//! ]
+//!
+//! [[router]]
+//! name = "web"
+//! interfaces = ["{subnet:web-v4}"]
+//! ```
+//!
+//! Rules of a security group have no name, each one is created unless a live rule of the group
+//! has the same fields. Likewise an interface of a router is added unless the router has one
+//! on the subnet already, while interfaces not listed are never removed.
+use crate::rest::ledger::delete_rank;
+use anyhow::{anyhow, Error};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+/// Resources grouped by type
+pub type Resources = BTreeMap<String, Vec<Map<String, Value>>>;
+
+/// IDs of resources by type and name
+pub type Ids = HashMap<(String, String), Vec<String>>;
+
+pub struct Manifest {
+    pub resources: Resources,
+}
+
+impl Manifest {
+    /// Read TOML, YAML or JSON5 according to file extension
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let data = fs::read_to_string(path)?;
+        let format = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        Self::parse(&data, format)
+    }
+
+    /// Parse TOML, YAML or JSON5 by format name
+    pub fn parse(data: &str, format: &str) -> Result<Self, Error> {
+        let resources: Resources = match format {
+            "toml" => toml::from_str(data)?,
+            "yaml" | "yml" => serde_norway::from_str(data)?,
+            _ => json5::from_str(data)
+                .map_err(|e| anyhow!("Failed to parse manifest file - {}", e))?,
+        };
+        let manifest = Self { resources };
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Write TOML, YAML or JSON according to file extension
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let format = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        fs::write(path, self.to_string(format)?)?;
+        Ok(())
+    }
+
+    pub fn to_string(&self, format: &str) -> Result<String, Error> {
+        Ok(match format {
+            "toml" => toml::to_string(&self.resources)?,
+            "yaml" | "yml" => serde_norway::to_string(&self.resources)?,
+            _ => serde_json::to_string_pretty(&self.resources)?,
+        })
+    }
+
+    /// Every resource needs a name unique in its type
+    fn validate(&self) -> Result<(), Error> {
+        for (res_type, items) in &self.resources {
+            let mut names = HashSet::new();
+            for item in items {
+                let name = name_of(item)
+                    .ok_or_else(|| anyhow!("A {} in manifest has no name", res_type))?;
+                if !names.insert(name) {
+                    return Err(anyhow!("Duplicated {} `{}` in manifest", res_type, name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Types referenced by resources, besides types listed in manifest
+    pub fn referenced_types(&self) -> Vec<String> {
+        let mut refs = Vec::new();
+        for item in self.resources.values().flatten() {
+            item.values().for_each(|v| references(v, &mut refs));
+        }
+        let mut types: Vec<_> = refs.into_iter().map(|(t, _)| t).collect();
+        types.sort();
+        types.dedup();
+        types
+    }
+}
+
+pub fn name_of(item: &Map<String, Value>) -> Option<&str> {
+    item.get("name")
+        .and_then(|n| n.as_str())
+        .filter(|n| !n.is_empty())
+}
+
+/// Types in the order they can be created, reverse of deleting
+pub fn create_order<'a>(types: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut types: Vec<_> = types.collect();
+    types.sort_by_key(|t| std::cmp::Reverse(delete_rank(t)));
+    types
+}
+
+pub fn reference(res_type: &str, name: &str) -> String {
+    format!("{{{res_type}:{name}}}")
+}
+
+/// Type and name in `{type:name}`
+pub fn parse_reference(s: &str) -> Option<(&str, &str)> {
+    s.strip_prefix('{')?.strip_suffix('}')?.split_once(':')
+}
+
+fn references(v: &Value, refs: &mut Vec<(String, String)>) {
+    match v {
+        Value::String(s) => {
+            if let Some((t, n)) = parse_reference(s) {
+                refs.push((t.to_string(), n.to_string()));
+            }
+        }
+        Value::Array(a) => a.iter().for_each(|v| references(v, refs)),
+        Value::Object(o) => o.values().for_each(|v| references(v, refs)),
+        _ => (),
+    }
+}
+
+/// Replace references with IDs. Unknown ones are kept as they are unless `strict`.
+pub fn resolve(v: &Value, ids: &Ids, strict: bool) -> Result<Value, Error> {
+    Ok(match v {
+        Value::String(s) => match parse_reference(s) {
+            Some((t, n)) => match ids.get(&(t.to_string(), n.to_string())).map(|i| &i[..]) {
+                Some([id]) => Value::String(id.clone()),
+                Some([_, _, ..]) => return Err(anyhow!("Found multiple {} named `{}`", t, n)),
+                _ if strict => return Err(anyhow!("{} `{}` not found", t, n)),
+                _ => v.clone(),
+            },
+            None => v.clone(),
+        },
+        Value::Array(a) => Value::Array(
+            a.iter()
+                .map(|v| resolve(v, ids, strict))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(o) => Value::Object(
+            o.iter()
+                .map(|(k, v)| Ok((k.clone(), resolve(v, ids, strict)?)))
+                .collect::<Result<_, Error>>()?,
+        ),
+        _ => v.clone(),
+    })
+}
@@ -1,3 +1,13 @@
+pub mod cascade;
+pub mod filter;
+pub mod ledger;
+pub mod loadbalance;
+pub mod metrics;
 pub mod output;
+pub mod path;
 pub mod resource;
+#[allow(clippy::module_inception)]
 pub mod rest;
+pub mod token;
+pub mod topology;
+pub mod watch;
@@ -0,0 +1,493 @@
+//! `delete --cascade`, which finds resources depending on the ones to be deleted,
+//! shows them as a plan, and deletes them bottom up after confirmation.
+use crate::{
+    config::{self, Config},
+    rest::ledger::{delete_rank, Ledger},
+    rest::output::{note, quiet},
+    rest::resource::ResourceBuilder,
+    rest::rest::{same_id, HttpError, Rest},
+};
+use anyhow::{anyhow, Error};
+use reqwest::StatusCode;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    io::{self, IsTerminal, Write},
+};
+use uuid::Uuid;
+
+/// What to do with a resource in the plan
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Router interface port, which can't be deleted directly
+    RemoveInterface { router: Uuid, port: Uuid },
+    /// Router gateway on a network to be deleted
+    ClearGateway { router: Uuid },
+    /// Floating IP of a port to be deleted, which isn't deleted itself
+    Disassociate { fip: Uuid },
+    Delete {
+        res_type: String,
+        uri: String,
+        id: Uuid,
+        name: String,
+    },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::RemoveInterface { router, port } => {
+                write!(f, "remove interface port {port} from router {router}")
+            }
+            Step::ClearGateway { router } => write!(f, "clear gateway of router {router}"),
+            Step::Disassociate { fip } => write!(f, "disassociate floatingip {fip}"),
+            Step::Delete {
+                res_type, id, name, ..
+            } => {
+                let name = if name.is_empty() { "-" } else { name };
+                write!(f, "delete {res_type} {name} ({id})")
+            }
+        }
+    }
+}
+
+impl Step {
+    /// Detaching goes first, then deleting from dependents to what they depend on
+    fn rank(&self) -> usize {
+        match self {
+            Step::RemoveInterface { .. } => 0,
+            Step::ClearGateway { .. } => 1,
+            Step::Disassociate { .. } => 2,
+            Step::Delete { res_type, .. } => 3 + delete_rank(res_type),
+        }
+    }
+}
+
+fn id_of(value: &Value, key: &str) -> Option<Uuid> {
+    value[key].as_str().and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// Step for a port, by what owns it. None if it goes with its owner.
+pub(super) fn port_step(port: &Value, uri: &str) -> Option<Step> {
+    let id = id_of(port, "id")?;
+    let owner = port["device_owner"].as_str().unwrap_or_default();
+    match owner {
+        "network:router_interface" => Some(Step::RemoveInterface {
+            router: id_of(port, "device_id")?,
+            port: id,
+        }),
+        "network:router_gateway" => Some(Step::ClearGateway {
+            router: id_of(port, "device_id")?,
+        }),
+        "network:floatingip" => None,
+        o if o.starts_with("neutron:LOADBALANCER") => None,
+        _ => Some(Step::Delete {
+            res_type: String::from("port"),
+            uri: uri.to_string(),
+            id,
+            name: port["name"].as_str().unwrap_or_default().to_string(),
+        }),
+    }
+}
+
+/// Sort steps to be run, and drop detaching of what is deleted anyway
+pub fn order(steps: &mut Vec<Step>) {
+    let deleted: HashSet<Uuid> = steps
+        .iter()
+        .filter_map(|s| match s {
+            Step::Delete { id, .. } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    steps.retain(|s| match s {
+        Step::Disassociate { fip } => !deleted.contains(fip),
+        Step::ClearGateway { router } => !deleted.contains(router),
+        _ => true,
+    });
+    let mut seen = Vec::new();
+    steps.retain(|s| {
+        let keep = !seen.contains(s);
+        seen.push(s.clone());
+        keep
+    });
+    steps.sort_by_key(|s| s.rank());
+}
+
+pub(super) struct Cascade<'a> {
+    api: Rest,
+    cfg: &'a Config,
+    steps: Vec<Step>,
+}
+
+impl<'a> Cascade<'a> {
+    pub(super) fn new(cfg: &'a Config) -> Self {
+        let mut api = Rest::new(cfg);
+        api.set_bench(false);
+        Self {
+            api,
+            cfg,
+            steps: Vec::new(),
+        }
+    }
+
+    fn uri(&self, res_type: &str) -> String {
+        config::uri_of(&self.cfg.resource, res_type)
+    }
+
+    fn member_uri(&self, pool: Uuid) -> String {
+        let uri = self.uri("member");
+        if uri.contains("<pool_id>") {
+            uri.replace("<pool_id>", &pool.to_string())
+        } else {
+            format!("/neutron/pool/{pool}/member")
+        }
+    }
+
+    async fn call(
+        &mut self,
+        uri: &str,
+        res_type: &str,
+        oper: &str,
+        id: Option<Uuid>,
+        resource: Map<String, Value>,
+        filters: Option<Value>,
+    ) -> Result<Value, Error> {
+        let mut builder = ResourceBuilder::new();
+        builder.res_type(res_type).oper(oper).resource(resource);
+        if let Some(id) = id {
+            builder.id(id);
+        }
+        if let Some(filters) = filters {
+            builder.filters(filters);
+        }
+        let text = self.api.post(uri, builder.build()?).await?.text().await?;
+        Ok(serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    async fn read(&mut self, res_type: &str, id: Uuid) -> Result<Value, Error> {
+        let uri = self.uri(res_type);
+        self.call(&uri, res_type, "READ", Some(id), Map::new(), None)
+            .await
+    }
+
+    /// READALL with a filter of one field
+    async fn find(&mut self, res_type: &str, key: &str, id: Uuid) -> Result<Vec<Value>, Error> {
+        let uri = self.uri(res_type);
+        let filters = json!({ key: [id] });
+        let value = self
+            .call(&uri, res_type, "READALL", None, Map::new(), Some(filters))
+            .await?;
+        // Filter again, in case server ignores it
+        Ok(value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|v| same_id(&v[key], &id.to_string()))
+            .cloned()
+            .collect())
+    }
+
+    /// Members on a subnet in any pool, with the URI of their pool. They are found
+    /// through pools, since a member may be on another subnet than the VIP of its LB.
+    async fn members_on(&mut self, subnet: Uuid) -> Result<Vec<(String, Value)>, Error> {
+        let uri = self.uri("pool");
+        let pools = self
+            .call(&uri, "pool", "READALL", None, Map::new(), None)
+            .await?;
+        let pools: Vec<_> = pools
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|p| id_of(p, "id"))
+            .collect();
+        let mut found = Vec::new();
+        for pool in pools {
+            let uri = self.member_uri(pool);
+            let filters = json!({ "subnet_id": [subnet] });
+            let members = self
+                .call(&uri, "member", "READALL", None, Map::new(), Some(filters))
+                .await?;
+            for m in members.as_array().into_iter().flatten() {
+                if id_of(m, "subnet_id") == Some(subnet) {
+                    found.push((uri.clone(), m.clone()));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Walk dependents from the resources to be deleted
+    async fn discover(&mut self, res_type: &str, uri: &str, ids: &[Uuid]) -> Result<(), Error> {
+        let mut queue = VecDeque::new();
+        for id in ids {
+            let value = self
+                .call(uri, res_type, "READ", Some(*id), Map::new(), None)
+                .await?;
+            queue.push_back((res_type.to_string(), uri.to_string(), value));
+        }
+        let mut seen = HashSet::new();
+        while let Some((res_type, uri, value)) = queue.pop_front() {
+            let Some(id) = id_of(&value, "id") else {
+                continue;
+            };
+            if !seen.insert(id) {
+                continue;
+            }
+            let step = match res_type.as_str() {
+                "port" => port_step(&value, &uri),
+                _ => Some(Step::Delete {
+                    res_type: res_type.clone(),
+                    uri: uri.clone(),
+                    id,
+                    name: value["name"].as_str().unwrap_or_default().to_string(),
+                }),
+            };
+            // Only resources really deleted have dependents to be deleted
+            let deleted = matches!(step, Some(Step::Delete { .. }));
+            self.steps.extend(step);
+            if !deleted {
+                continue;
+            }
+
+            let mut dependents: Vec<(&str, Value)> = Vec::new();
+            match res_type.as_str() {
+                "network" => {
+                    for t in ["subnet", "port"] {
+                        for v in self.find(t, "network_id", id).await? {
+                            dependents.push((t, v));
+                        }
+                    }
+                    for v in self.find("floatingip", "floating_network_id", id).await? {
+                        dependents.push(("floatingip", v));
+                    }
+                }
+                "subnet" => {
+                    if let Some(network) = id_of(&value, "network_id") {
+                        for v in self.find("port", "network_id", network).await? {
+                            let on_subnet = v["fixed_ips"]
+                                .as_array()
+                                .into_iter()
+                                .flatten()
+                                .any(|ip| id_of(ip, "subnet_id") == Some(id));
+                            if on_subnet {
+                                dependents.push(("port", v));
+                            }
+                        }
+                    }
+                    for v in self.find("loadbalancer", "vip_subnet_id", id).await? {
+                        dependents.push(("loadbalancer", v));
+                    }
+                    for (uri, m) in self.members_on(id).await? {
+                        queue.push_back((String::from("member"), uri, m));
+                    }
+                }
+                "router" => {
+                    for v in self.find("port", "device_id", id).await? {
+                        dependents.push(("port", v));
+                    }
+                    for v in self.find("floatingip", "router_id", id).await? {
+                        self.steps
+                            .extend(id_of(&v, "id").map(|fip| Step::Disassociate { fip }));
+                    }
+                }
+                "port" => {
+                    for v in self.find("floatingip", "port_id", id).await? {
+                        self.steps
+                            .extend(id_of(&v, "id").map(|fip| Step::Disassociate { fip }));
+                    }
+                }
+                "loadbalancer" => {
+                    for (key, t) in [("listeners", "listener"), ("pools", "pool")] {
+                        let children: Vec<_> = value[key]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|c| id_of(c, "id"))
+                            .collect();
+                        for child in children {
+                            dependents.push((t, self.read(t, child).await?));
+                        }
+                    }
+                }
+                "pool" => {
+                    let uri = self.member_uri(id);
+                    let members = self
+                        .call(&uri, "member", "READALL", None, Map::new(), None)
+                        .await?;
+                    for m in members.as_array().into_iter().flatten() {
+                        queue.push_back((String::from("member"), uri.clone(), m.clone()));
+                    }
+                    if let Some(hm) = id_of(&value, "healthmonitor_id") {
+                        dependents.push(("healthmonitor", self.read("healthmonitor", hm).await?));
+                    }
+                }
+                _ => (),
+            }
+            for (t, v) in dependents {
+                queue.push_back((t.to_string(), self.uri(t), v));
+            }
+        }
+        Ok(())
+    }
+
+    /// Interfaces to be removed before a router, or a router interface port, is deleted.
+    /// Nothing if the resource is gone already.
+    pub(super) async fn detach(&mut self, res_type: &str, id: Uuid) -> Result<Vec<Step>, Error> {
+        let ports = match res_type {
+            "router" => self.find("port", "device_id", id).await,
+            "port" => self.read("port", id).await.map(|port| vec![port]),
+            _ => Ok(Vec::new()),
+        };
+        let ports = match ports {
+            Ok(ports) => ports,
+            Err(e)
+                if e.downcast_ref::<HttpError>()
+                    .is_some_and(|e| e.status == StatusCode::NOT_FOUND) =>
+            {
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
+        let uri = self.uri("port");
+        Ok(ports
+            .iter()
+            .filter_map(|port| port_step(port, &uri))
+            .filter(|step| matches!(step, Step::RemoveInterface { .. }))
+            .collect())
+    }
+
+    pub(super) async fn run(&mut self, step: &Step, ledger: &Ledger) -> Result<(), Error> {
+        match step {
+            Step::RemoveInterface { router, port } => {
+                let uri = self.uri("router");
+                let mut attr = Map::new();
+                attr.insert(String::from("port_id"), json!(port));
+                self.call(&uri, "router", "DELINTERFACE", Some(*router), attr, None)
+                    .await?;
+                ledger.deleted("port", &self.uri("port"), *port);
+            }
+            Step::ClearGateway { router } => {
+                let uri = self.uri("router");
+                let mut attr = Map::new();
+                attr.insert(String::from("external_gateway_info"), json!({}));
+                self.call(&uri, "router", "UPDATE", Some(*router), attr, None)
+                    .await?;
+            }
+            Step::Disassociate { fip } => {
+                let uri = self.uri("floatingip");
+                let mut attr = Map::new();
+                attr.insert(String::from("port_id"), Value::Null);
+                self.call(&uri, "floatingip", "UPDATE", Some(*fip), attr, None)
+                    .await?;
+            }
+            Step::Delete {
+                res_type, uri, id, ..
+            } => {
+                self.call(uri, res_type, "DELETE", Some(*id), Map::new(), None)
+                    .await?;
+                ledger.deleted(res_type, uri, *id);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ask on terminal, `--yes` is needed otherwise
+pub fn confirm(question: &str) -> Result<bool, Error> {
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!("Confirmation is needed, use --yes"));
+    }
+    if quiet() {
+        eprint!("{question} [y/N] ");
+        io::stderr().flush()?;
+    } else {
+        print!("{question} [y/N] ");
+        io::stdout().flush()?;
+    }
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Delete resources with all their dependents
+pub async fn delete(
+    cfg: &Config,
+    res_type: &str,
+    uri: &str,
+    ids: &[Uuid],
+    yes: bool,
+) -> Result<(), Error> {
+    let mut cascade = Cascade::new(cfg);
+    cascade.discover(res_type, uri, ids).await?;
+    let mut steps = std::mem::take(&mut cascade.steps);
+    order(&mut steps);
+
+    note("Plan:");
+    for (i, step) in steps.iter().enumerate() {
+        note(format!("  {}. {}", i + 1, step));
+    }
+    if !yes && !confirm(&format!("Run {} steps above?", steps.len()))? {
+        note("Cancelled");
+        return Ok(());
+    }
+
+    let ledger = Ledger::new()?;
+    for (i, step) in steps.iter().enumerate() {
+        match cascade.run(step, &ledger).await {
+            Ok(()) => note(format!("[{}/{}] {} ... done", i + 1, steps.len(), step)),
+            Err(e) => {
+                eprintln!("[{}/{}] {} ... failed", i + 1, steps.len(), step);
+                return Err(e.context(format!("{} step(s) left", steps.len() - i)));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order() {
+        let id = |n: u128| Uuid::from_u128(n);
+        let delete = |res_type: &str, n: u128| Step::Delete {
+            res_type: res_type.to_string(),
+            uri: format!("/neutron/{res_type}"),
+            id: id(n),
+            name: String::new(),
+        };
+        let interface = json!({
+            "id": id(3).to_string(), "device_id": id(9).to_string(),
+            "device_owner": "network:router_interface",
+        });
+        let fip_port = json!({"id": id(5).to_string(), "device_owner": "network:floatingip"});
+        let mut steps = vec![
+            delete("network", 1),
+            delete("subnet", 2),
+            delete("member", 8),
+            port_step(&interface, "/neutron/port").unwrap(),
+            delete("port", 4),
+            delete("floatingip", 6),
+            Step::Disassociate { fip: id(6) },
+            Step::Disassociate { fip: id(7) },
+            delete("port", 4),
+        ];
+        assert_eq!(port_step(&fip_port, "/neutron/port"), None);
+        order(&mut steps);
+        let plan: Vec<_> = steps.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            plan,
+            [
+                format!("remove interface port {} from router {}", id(3), id(9)),
+                format!("disassociate floatingip {}", id(7)),
+                format!("delete floatingip - ({})", id(6)),
+                format!("delete member - ({})", id(8)),
+                format!("delete port - ({})", id(4)),
+                format!("delete subnet - ({})", id(2)),
+                format!("delete network - ({})", id(1)),
+            ]
+        );
+    }
+}
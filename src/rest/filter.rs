@@ -0,0 +1,205 @@
+//! `--where` expressions for `list`, e.g. `name=web*`, `admin_state_up=true`,
+//! `id~=^6bd0`, `mtu>=1450` or `binding:vif_details.port_filter=true`.
+//!
+//! Plain equality on a top level field is sent to server as filter, unless its value looks
+//! like a bool or number, which server compares by type. All expressions are also checked
+//! on response, which covers what server doesn't support.
+use super::path::{lookup, root};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone)]
+enum Op {
+    /// Equal, `*` matches any characters
+    Eq,
+    Ne,
+    /// Regex search
+    Match(Regex),
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cond {
+    path: String,
+    op: Op,
+    /// Kept as given, numbers are parsed from it only to be ordered
+    value: String,
+}
+
+/// Operators with two characters come first
+const OPS: [&str; 7] = ["!=", "~=", ">=", "<=", "=", ">", "<"];
+
+pub fn where_parser(s: &str) -> Result<Cond, String> {
+    let (pos, op) = OPS
+        .iter()
+        .filter_map(|op| s.find(op).map(|pos| (pos, *op)))
+        .min_by_key(|(pos, op)| (*pos, std::cmp::Reverse(op.len())))
+        .ok_or_else(|| format!("no operator found in `{s}`, use one of {}", OPS.join(" ")))?;
+    let path = s[..pos].trim();
+    if path.is_empty() {
+        return Err(format!("no field found in `{s}`"));
+    }
+    let raw = s[pos + op.len()..].trim();
+    let op = match op {
+        "=" => Op::Eq,
+        "!=" => Op::Ne,
+        "~=" => Op::Match(Regex::new(raw).map_err(|e| e.to_string())?),
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        "<" => Op::Lt,
+        _ => Op::Le,
+    };
+    Ok(Cond {
+        path: path.to_string(),
+        op,
+        value: raw.to_string(),
+    })
+}
+
+fn text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn number(v: &Value) -> Option<f64> {
+    v.as_f64()
+        .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+}
+
+fn glob(pattern: &str, s: &str) -> bool {
+    let parts: Vec<_> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == s;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !s.starts_with(first) || !s[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &s[first.len()..s.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+impl Cond {
+    /// Equality of a string which can be sent to server. Server compares values by type,
+    /// so it's unknown whether `true` or `2024` is a string there.
+    fn is_server_side(&self) -> bool {
+        matches!(self.op, Op::Eq)
+            && root(&self.path) == self.path
+            && !self.value.contains('*')
+            && self.value.parse::<bool>().is_err()
+            && self.value.parse::<f64>().is_err()
+    }
+
+    fn equals(&self, v: &Value) -> bool {
+        glob(&self.value, &text(v))
+    }
+
+    pub fn matches(&self, item: &Value) -> bool {
+        let Some(v) = lookup(item, &self.path) else {
+            return matches!(self.op, Op::Ne);
+        };
+        let order = || Some(number(v)?.total_cmp(&self.value.parse().ok()?));
+        match &self.op {
+            Op::Eq => self.equals(v),
+            Op::Ne => !self.equals(v),
+            Op::Match(re) => re.is_match(&text(v)),
+            Op::Gt => order() == Some(Ordering::Greater),
+            Op::Ge => matches!(order(), Some(Ordering::Greater | Ordering::Equal)),
+            Op::Lt => order() == Some(Ordering::Less),
+            Op::Le => matches!(order(), Some(Ordering::Less | Ordering::Equal)),
+        }
+    }
+}
+
+/// Add equalities to filter object of request, values of one field are in a list
+pub fn server_filters(conds: &[Cond], filters: Option<Value>) -> Option<Value> {
+    let mut map = match filters {
+        Some(Value::Object(map)) => map,
+        Some(filters) => return Some(filters),
+        None => Map::new(),
+    };
+    for cond in conds.iter().filter(|c| c.is_server_side()) {
+        // Several values of one field means OR for server, so leave them to client
+        if map.contains_key(&cond.path) {
+            continue;
+        }
+        map.insert(cond.path.clone(), json!([cond.value]));
+    }
+    (!map.is_empty()).then_some(Value::Object(map))
+}
+
+/// Top level fields needed to check expressions
+pub fn fields(conds: &[Cond]) -> Vec<String> {
+    conds.iter().map(|c| root(&c.path).to_string()).collect()
+}
+
+pub fn matches_all(conds: &[Cond], item: &Value) -> bool {
+    conds.iter().all(|c| c.matches(item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_where() {
+        let conds: Vec<_> = [
+            "name=web*",
+            "admin_state_up=true",
+            "id~=^6bd0",
+            "mtu>=1450",
+            "binding:vif_details.port_filter!=false",
+        ]
+        .iter()
+        .map(|s| where_parser(s).unwrap())
+        .collect();
+        let port = json!({
+            "id": "6bd0768b-0beb-4b30-9916-a3c445fede1c",
+            "name": "web-1",
+            "admin_state_up": true,
+            "mtu": "1500",
+            "binding:vif_details": {"port_filter": true},
+        });
+        assert!(matches_all(&conds, &port));
+        assert!(!where_parser("mtu<1450").unwrap().matches(&port));
+        assert!(where_parser("missing!=1").unwrap().matches(&port));
+        assert!(where_parser("=1").is_err());
+        assert_eq!(
+            server_filters(&conds, Some(json!({"limit": 10}))),
+            Some(json!({"limit": 10}))
+        );
+
+        // Values looking like a number or bool are checked by client only
+        let conds = [
+            where_parser("name=2024").unwrap(),
+            where_parser("mtu>1e3").unwrap(),
+            where_parser("status=ACTIVE").unwrap(),
+        ];
+        assert_eq!(
+            server_filters(&conds, None),
+            Some(json!({"status": ["ACTIVE"]}))
+        );
+        assert!(matches_all(
+            &conds,
+            &json!({"name": "2024", "mtu": 1500, "status": "ACTIVE"})
+        ));
+        assert!(!conds[0].matches(&json!({"name": "20240"})));
+        assert!(
+            where_parser("enabled=true")
+                .unwrap()
+                .matches(&json!({"enabled": true}))
+        );
+    }
+}
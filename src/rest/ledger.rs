@@ -0,0 +1,338 @@
+use super::cascade::{confirm, order, Cascade};
+use crate::{
+    config,
+    rest::output::{json_to_table, note},
+    rest::resource::ResourceBuilder,
+    rest::rest::{HttpError, Rest},
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use log::warn;
+use reqwest::{self, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+use uuid::Uuid;
+
+/// Resource types in the order they can be deleted safely.
+/// Unknown types are deleted last, since they may refer to any of these.
+const DELETE_ORDER: [&str; 20] = [
+    "floatingip",
+    "member",
+    "healthmonitor",
+    "pool",
+    "listener",
+    "loadbalancer",
+    "port",
+    "router",
+    "subnet",
+    "network",
+    "security_group_rule",
+    "security_group",
+    "firewall_group",
+    "firewall_policy",
+    "firewall_rule",
+    "segment_firewall_group",
+    "segment_firewall_policy",
+    "segment_firewall_rule",
+    "tag",
+    "net_provider",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Create,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    action: Action,
+    #[serde(rename = "type")]
+    pub res_type: String,
+    pub uri: String,
+    pub id: Uuid,
+    pub timestamp: String,
+}
+
+/// Append-only record of resources created and deleted by sdncli.
+/// Each line is a JSON entry appended under a lock, so concurrent writers don't corrupt each other.
+#[derive(Clone)]
+pub struct Ledger {
+    path: PathBuf,
+}
+
+impl Ledger {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            path: config::ledger_file()?,
+        })
+    }
+
+    pub fn created(&self, res_type: &str, uri: &str, id: Uuid) {
+        self.append(Action::Create, res_type, uri, id);
+    }
+
+    /// Record a resource from CREATE response
+    pub fn created_value(&self, res_type: &str, uri: &str, value: &Value) {
+        if let Some(id) = value["id"].as_str().and_then(|id| Uuid::parse_str(id).ok()) {
+            self.created(res_type, uri, id);
+        }
+    }
+
+    pub fn deleted(&self, res_type: &str, uri: &str, id: Uuid) {
+        self.append(Action::Delete, res_type, uri, id);
+    }
+
+    fn append(&self, action: Action, res_type: &str, uri: &str, id: Uuid) {
+        let entry = Entry {
+            action,
+            res_type: res_type.to_string(),
+            uri: uri.to_string(),
+            id,
+            timestamp: Utc::now().to_rfc3339(),
+        };
+        let write = || -> anyhow::Result<()> {
+            let line = format!("{}\n", serde_json::to_string(&entry)?);
+            let _lock = self.lock()?;
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?
+                .write_all(line.as_bytes())?;
+            Ok(())
+        };
+        if let Err(e) = write() {
+            warn!("Failed to record {} in {:?} - {}", id, self.path, e);
+        }
+    }
+
+    /// Held while appending or rewriting, other invocations wait for it meanwhile
+    fn lock(&self) -> io::Result<fs::File> {
+        let lock = fs::File::create(self.path.with_extension("lock"))?;
+        // Released when the file is closed
+        lock.lock()?;
+        Ok(lock)
+    }
+
+    fn parse(&self, data: &str) -> anyhow::Result<Vec<Entry>> {
+        data.lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+            .map(|(n, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| anyhow!("{:?} line {}: {}", self.path, n + 1, e))
+            })
+            .collect()
+    }
+
+    /// Resources created but not deleted yet in creation order, with length of the file read
+    /// to find lines appended later
+    fn entries(&self) -> anyhow::Result<(Vec<Entry>, usize)> {
+        let data = match fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(e.into()),
+        };
+        Ok((replay(self.parse(&data)?), data.len()))
+    }
+
+    /// Rewrite ledger with only resources left, and those of lines appended since `len` bytes
+    /// were read, e.g. by stress. It's written to a temporary file then renamed under the lock,
+    /// so neither readers see a partial ledger nor lines appended meanwhile are lost.
+    fn compact(&self, entries: &[Entry], len: usize) -> anyhow::Result<()> {
+        let _lock = self.lock()?;
+        let current = fs::read_to_string(&self.path).unwrap_or_default();
+        let appended = self.parse(current.get(len..).unwrap_or_default())?;
+        let mut data = String::new();
+        for entry in replay(entries.iter().cloned().chain(appended)) {
+            data.push_str(&serde_json::to_string(&entry)?);
+            data.push('\n');
+        }
+        let tmp = self
+            .path
+            .with_extension(format!("jsonl.{}", std::process::id()));
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(data.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Drop resources created and deleted already, like those of a stress run
+    pub fn tidy(&self) -> anyhow::Result<()> {
+        let (entries, len) = self.entries()?;
+        self.compact(&entries, len)
+    }
+}
+
+/// Resources created but not deleted in the order of entries
+fn replay(entries: impl IntoIterator<Item = Entry>) -> Vec<Entry> {
+    let mut left: Vec<Entry> = Vec::new();
+    for entry in entries {
+        match entry.action {
+            Action::Create => left.push(entry),
+            Action::Delete => left.retain(|e| e.id != entry.id),
+        }
+    }
+    left
+}
+
+/// Position of a type in `DELETE_ORDER`, after all of them for unknown types
+pub fn delete_rank(res_type: &str) -> usize {
+    DELETE_ORDER
+        .iter()
+        .position(|t| *t == res_type)
+        .unwrap_or(DELETE_ORDER.len())
+}
+
+/// Sort entries so that dependents are deleted before what they depend on
+pub fn delete_order(entries: &mut [Entry]) {
+    // Newest first inside one type
+    entries.reverse();
+    entries.sort_by_key(|e| delete_rank(&e.res_type));
+}
+
+/// Delete everything recorded in the ledger
+pub async fn cleanup(cfg: &config::Config, dry_run: bool, yes: bool) -> anyhow::Result<()> {
+    let ledger = Ledger::new()?;
+    let (mut entries, len) = ledger.entries()?;
+    if entries.is_empty() {
+        note(format!("Nothing to clean up in {:?}", ledger.path));
+        return Ok(());
+    }
+    delete_order(&mut entries);
+    json_to_table(
+        &json!(entries),
+        Some(vec![
+            String::from("type"),
+            String::from("id"),
+            String::from("uri"),
+            String::from("timestamp"),
+        ]),
+    );
+    if dry_run {
+        return Ok(());
+    }
+    if !yes && !confirm(&format!("Delete {} resources above?", entries.len()))? {
+        note("Cancelled");
+        return Ok(());
+    }
+
+    // Router interfaces block deleting routers, ports and subnets
+    let mut cascade = Cascade::new(cfg);
+    let mut detach = Vec::new();
+    for entry in &entries {
+        match cascade.detach(&entry.res_type, entry.id).await {
+            Ok(steps) => detach.extend(steps),
+            Err(e) => warn!(
+                "Failed to find interfaces of {} {} - {}",
+                entry.res_type, entry.id, e
+            ),
+        }
+    }
+    order(&mut detach);
+    for step in &detach {
+        match cascade.run(step, &ledger).await {
+            Ok(()) => note(format!("Done {step}")),
+            Err(e) => eprintln!("Failed {step} - {e}"),
+        }
+    }
+
+    let mut api = Rest::new(cfg);
+    let mut left = Vec::new();
+    for entry in entries {
+        let body = ResourceBuilder::new()
+            .res_type(&entry.res_type)
+            .oper("DELETE")
+            .id(entry.id)
+            .build()?;
+        match api
+            .send(reqwest::Method::POST, &entry.uri, Some(body), false)
+            .await
+        {
+            Ok(_) => note(format!("Deleted {} {}", entry.res_type, entry.id)),
+            Err(e) => match e.downcast_ref::<HttpError>() {
+                Some(err) if err.status == StatusCode::NOT_FOUND => {
+                    note(format!("Gone {} {}", entry.res_type, entry.id))
+                }
+                _ => {
+                    eprintln!("Failed {} {} - {}", entry.res_type, entry.id, e);
+                    left.push(entry);
+                }
+            },
+        }
+    }
+    // Keep creation order for the next run
+    left.reverse();
+    ledger.compact(&left, len)?;
+    if !left.is_empty() {
+        return Err(anyhow!(
+            "{} resource(s) left in {:?}",
+            left.len(),
+            ledger.path
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_order() {
+        let entry = |res_type: &str| Entry {
+            action: Action::Create,
+            res_type: res_type.to_string(),
+            uri: format!("/neutron/{res_type}"),
+            id: Uuid::new_v4(),
+            timestamp: String::new(),
+        };
+        let mut entries = vec![
+            entry("network"),
+            entry("subnet"),
+            entry("port"),
+            entry("port"),
+            entry("unknown"),
+        ];
+        let second_port = entries[3].id;
+        delete_order(&mut entries);
+        let types: Vec<_> = entries.iter().map(|e| e.res_type.as_str()).collect();
+        assert_eq!(types, ["port", "port", "subnet", "network", "unknown"]);
+        assert_eq!(entries[0].id, second_port);
+    }
+
+    #[test]
+    fn test_compact() {
+        let ledger = Ledger {
+            path: std::env::temp_dir().join(format!("sdncli-ledger-{}.jsonl", Uuid::new_v4())),
+        };
+        let [a, b, c, d] = [(); 4].map(|_| Uuid::new_v4());
+        ledger.created("network", "/neutron/network", a);
+        ledger.created("network", "/neutron/network", b);
+        let (entries, len) = ledger.entries().unwrap();
+        // Appended by another process while cleaning up
+        ledger.created("port", "/neutron/port", c);
+        ledger.created("port", "/neutron/port", d);
+        ledger.deleted("port", "/neutron/port", d);
+        ledger.compact(&entries[1..], len).unwrap();
+        let ids: Vec<_> = ledger.entries().unwrap().0.iter().map(|e| e.id).collect();
+        assert_eq!(ids, [b, c]);
+        assert_eq!(fs::read_to_string(&ledger.path).unwrap().lines().count(), 2);
+
+        // Created and deleted pairs are dropped
+        ledger.deleted("network", "/neutron/network", b);
+        ledger.tidy().unwrap();
+        let data = fs::read_to_string(&ledger.path).unwrap();
+        let ids: Vec<_> = ledger.parse(&data).unwrap().iter().map(|e| e.id).collect();
+        assert_eq!(ids, [c]);
+        fs::remove_file(&ledger.path).unwrap();
+        fs::remove_file(ledger.path.with_extension("lock")).unwrap();
+    }
+}
@@ -0,0 +1,564 @@
+use crate::{
+    cli::{LbCreateOpts, LbOperations},
+    config::{self, Config},
+    rest::ledger::Ledger,
+    rest::output::{note, print_output, Node, OutputFormat},
+    rest::resource::ResourceBuilder,
+    rest::rest::Rest,
+};
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{net::SocketAddr, time::Duration};
+use uuid::Uuid;
+
+/// Seconds to wait for a loadbalancer to leave PENDING_* status
+const WAIT_SECS: u64 = 300;
+
+/// A loadbalancer with everything belongs to it
+#[derive(Serialize)]
+struct Stack {
+    loadbalancer: Value,
+    listeners: Vec<Value>,
+    pools: Vec<PoolStack>,
+}
+
+#[derive(Serialize)]
+struct PoolStack {
+    pool: Value,
+    members: Vec<Value>,
+    healthmonitor: Option<Value>,
+}
+
+/// Node of tree view for a resource of the stack
+fn node(res_type: &str, value: &Value, detail: String) -> Node {
+    let name = value["name"].as_str().filter(|n| !n.is_empty());
+    let mut label = format!("{} {}", res_type, name.unwrap_or("-"));
+    label.push_str(&format!(" ({})", value["id"].as_str().unwrap_or_default()));
+    if !detail.is_empty() {
+        label.push_str(&format!(" {detail}"));
+    }
+    if let Some(status) = value["provisioning_status"].as_str() {
+        label.push_str(&format!(" [{status}]"));
+    }
+    Node {
+        label,
+        children: Vec::new(),
+    }
+}
+
+/// IDs in a list of `{"id": ...}`
+fn ids(value: &Value) -> Vec<&str> {
+    value
+        .as_array()
+        .map(|a| a.iter().filter_map(|v| v["id"].as_str()).collect())
+        .unwrap_or_default()
+}
+
+/// Address and port of a member, the port is optional. IPv6 addresses with a port are
+/// bracketed like `[fd00::10]:8080`, and an unbracketed IPv6 address has no port.
+fn parse_member(member: &str, port: u16) -> Result<(String, u16), Error> {
+    if let Ok(addr) = member.parse::<SocketAddr>() {
+        return Ok((addr.ip().to_string(), addr.port()));
+    }
+    if member.matches(':').count() > 1 {
+        let address = member.trim_start_matches('[').trim_end_matches(']');
+        return Ok((address.to_string(), port));
+    }
+    match member.rsplit_once(':') {
+        Some((a, p)) => Ok((
+            a.to_string(),
+            p.parse()
+                .map_err(|_| anyhow!("Invalid member `{}`", member))?,
+        )),
+        None => Ok((member.to_string(), port)),
+    }
+}
+
+impl Stack {
+    fn tree(&self) -> Node {
+        let lb = &self.loadbalancer;
+        let mut root = node(
+            "loadbalancer",
+            lb,
+            format!("vip {}", lb["vip_address"].as_str().unwrap_or("-")),
+        );
+        let mut listeners: Vec<Node> = self
+            .listeners
+            .iter()
+            .map(|l| {
+                node(
+                    "listener",
+                    l,
+                    format!(
+                        "{}:{}",
+                        l["protocol"].as_str().unwrap_or("-"),
+                        l["protocol_port"]
+                    ),
+                )
+            })
+            .collect();
+        for p in &self.pools {
+            let mut pool = node(
+                "pool",
+                &p.pool,
+                format!(
+                    "{} {}",
+                    p.pool["protocol"].as_str().unwrap_or("-"),
+                    p.pool["lb_algorithm"].as_str().unwrap_or("-")
+                ),
+            );
+            for m in &p.members {
+                pool.children.push(node(
+                    "member",
+                    m,
+                    format!(
+                        "{}:{}",
+                        m["address"].as_str().unwrap_or("-"),
+                        m["protocol_port"]
+                    ),
+                ));
+            }
+            if let Some(hm) = &p.healthmonitor {
+                pool.children.push(node(
+                    "healthmonitor",
+                    hm,
+                    hm["type"].as_str().unwrap_or_default().to_string(),
+                ));
+            }
+            // Pool is displayed under the listener using it, or under loadbalancer
+            let pool_id = p.pool["id"].as_str();
+            let owner = self.listeners.iter().position(|l| {
+                l["default_pool_id"].as_str() == pool_id
+                    || ids(&p.pool["listeners"]).contains(&l["id"].as_str().unwrap_or_default())
+            });
+            match owner {
+                Some(i) => listeners[i].children.push(pool),
+                None => root.children.push(pool),
+            }
+        }
+        root.children.splice(0..0, listeners);
+        root
+    }
+}
+
+struct Lb<'a> {
+    api: Rest,
+    cfg: &'a Config,
+    ledger: Ledger,
+}
+
+impl<'a> Lb<'a> {
+    fn new(cfg: &'a Config) -> Result<Self, Error> {
+        let mut api = Rest::new(cfg);
+        api.set_bench(false);
+        Ok(Self {
+            api,
+            cfg,
+            ledger: Ledger::new()?,
+        })
+    }
+
+    fn uri(&self, res_type: &str) -> String {
+        config::uri_of(&self.cfg.resource, res_type)
+    }
+
+    fn member_uri(&self, pool: &str) -> String {
+        let uri = self.uri("member");
+        if uri.contains("<pool_id>") {
+            uri.replace("<pool_id>", pool)
+        } else {
+            format!("/neutron/pool/{pool}/member")
+        }
+    }
+
+    async fn call(
+        &mut self,
+        uri: &str,
+        res_type: &str,
+        oper: &str,
+        id: Option<Uuid>,
+        attr: Map<String, Value>,
+    ) -> Result<Value, Error> {
+        let mut builder = ResourceBuilder::new();
+        builder.res_type(res_type).oper(oper).resource(attr);
+        if let Some(id) = id {
+            builder.id(id);
+        }
+        let text = self.api.post(uri, builder.build()?).await?.text().await?;
+        Ok(serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    async fn create(
+        &mut self,
+        uri: &str,
+        res_type: &str,
+        attr: Value,
+        created: &mut Vec<(String, String, Uuid)>,
+    ) -> Result<Uuid, Error> {
+        let attr = attr.as_object().cloned().unwrap_or_default();
+        let value = self.call(uri, res_type, "CREATE", None, attr).await?;
+        let id = value["id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| anyhow!("No ID found in response of creating {}", res_type))?;
+        self.ledger.created(res_type, uri, id);
+        note(format!("Created {} {}", res_type, id));
+        created.push((uri.to_string(), res_type.to_string(), id));
+        Ok(id)
+    }
+
+    async fn delete(&mut self, uri: &str, res_type: &str, id: Uuid) -> Result<(), Error> {
+        self.call(uri, res_type, "DELETE", Some(id), Map::new())
+            .await?;
+        self.ledger.deleted(res_type, uri, id);
+        note(format!("Deleted {} {}", res_type, id));
+        Ok(())
+    }
+
+    async fn read(&mut self, uri: &str, res_type: &str, id: &str) -> Result<Value, Error> {
+        let id = Uuid::parse_str(id)?;
+        self.call(uri, res_type, "READ", Some(id), Map::new()).await
+    }
+
+    async fn read_all(&mut self, uri: &str, res_type: &str) -> Result<Vec<Value>, Error> {
+        let value = self
+            .call(uri, res_type, "READALL", None, Map::new())
+            .await?;
+        Ok(value.as_array().cloned().unwrap_or_default())
+    }
+
+    /// Loadbalancer accepts no change until it leaves PENDING_* status
+    async fn wait(&mut self, lb: Uuid) -> Result<(), Error> {
+        for _ in 0..WAIT_SECS {
+            let value = self
+                .read(&self.uri("loadbalancer"), "loadbalancer", &lb.to_string())
+                .await?;
+            match value["provisioning_status"].as_str().unwrap_or_default() {
+                "ERROR" => return Err(anyhow!("Loadbalancer {} is in ERROR status", lb)),
+                s if s.starts_with("PENDING_") => tokio::time::sleep(Duration::from_secs(1)).await,
+                _ => return Ok(()),
+            }
+        }
+        Err(anyhow!(
+            "Loadbalancer {} is still pending after {}s",
+            lb,
+            WAIT_SECS
+        ))
+    }
+
+    async fn resolve(&mut self, res_type: &str, name: &str) -> Result<Uuid, Error> {
+        match Uuid::parse_str(name) {
+            Ok(id) => Ok(id),
+            Err(_) => self.api.name_to_id(&self.uri(res_type), name).await,
+        }
+    }
+
+    /// Read a loadbalancer and all its children
+    async fn stack(&mut self, id: Uuid) -> Result<Stack, Error> {
+        let loadbalancer = self
+            .read(&self.uri("loadbalancer"), "loadbalancer", &id.to_string())
+            .await?;
+        let mut listeners = Vec::new();
+        for l in ids(&loadbalancer["listeners"]) {
+            listeners.push(self.read(&self.uri("listener"), "listener", l).await?);
+        }
+        let mut pools = Vec::new();
+        for p in ids(&loadbalancer["pools"]) {
+            let pool = self.read(&self.uri("pool"), "pool", p).await?;
+            let members = self.read_all(&self.member_uri(p), "member").await?;
+            let healthmonitor = match pool["healthmonitor_id"].as_str() {
+                Some(hm) if !hm.is_empty() => Some(
+                    self.read(&self.uri("healthmonitor"), "healthmonitor", hm)
+                        .await?,
+                ),
+                _ => None,
+            };
+            pools.push(PoolStack {
+                pool,
+                members,
+                healthmonitor,
+            });
+        }
+        Ok(Stack {
+            loadbalancer,
+            listeners,
+            pools,
+        })
+    }
+
+    /// Create the whole stack, or remove what is created if any step fails
+    async fn create_stack(&mut self, opts: LbCreateOpts) -> Result<Uuid, Error> {
+        let LbCreateOpts {
+            name,
+            subnet,
+            protocol,
+            port,
+            algorithm,
+            member,
+            monitor,
+            delay,
+            timeout,
+            max_retries,
+            attr,
+        } = opts;
+        let subnet = self.resolve("subnet", &subnet).await?;
+        let mut lb_attr = Map::new();
+        for a in attr {
+            lb_attr.extend(a.as_object().unwrap().clone());
+        }
+        lb_attr.insert(String::from("name"), json!(name));
+        lb_attr.insert(String::from("vip_subnet_id"), json!(subnet));
+        if let Some(res) = self
+            .cfg
+            .resource
+            .iter()
+            .find(|r| r.resource == "loadbalancer")
+        {
+            res.apply_attr(&mut lb_attr, true)?;
+        }
+
+        let mut created = Vec::new();
+        let result: Result<Uuid, Error> = async {
+            let lb = self
+                .create(
+                    &self.uri("loadbalancer"),
+                    "loadbalancer",
+                    json!(lb_attr),
+                    &mut created,
+                )
+                .await?;
+            self.wait(lb).await?;
+            let listener = json!({
+                "name": format!("{name}-listener"),
+                "loadbalancer_id": lb,
+                "protocol": protocol,
+                "protocol_port": port,
+            });
+            let listener = self
+                .create(&self.uri("listener"), "listener", listener, &mut created)
+                .await?;
+            self.wait(lb).await?;
+            let pool = json!({
+                "name": format!("{name}-pool"),
+                "listener_id": listener,
+                "protocol": protocol,
+                "lb_algorithm": algorithm,
+            });
+            let pool = self
+                .create(&self.uri("pool"), "pool", pool, &mut created)
+                .await?;
+            self.wait(lb).await?;
+            for m in &member {
+                let (address, member_port) = parse_member(m, port)?;
+                let attr = json!({
+                    "address": address,
+                    "protocol_port": member_port,
+                    "subnet_id": subnet,
+                });
+                let uri = self.member_uri(&pool.to_string());
+                self.create(&uri, "member", attr, &mut created).await?;
+                self.wait(lb).await?;
+            }
+            if let Some(monitor) = &monitor {
+                let attr = json!({
+                    "name": format!("{name}-monitor"),
+                    "pool_id": pool,
+                    "type": monitor.to_uppercase(),
+                    "delay": delay,
+                    "timeout": timeout,
+                    "max_retries": max_retries,
+                });
+                self.create(
+                    &self.uri("healthmonitor"),
+                    "healthmonitor",
+                    attr,
+                    &mut created,
+                )
+                .await?;
+                self.wait(lb).await?;
+            }
+            Ok(lb)
+        }
+        .await;
+
+        if result.is_err() {
+            let lb = created.first().map(|(_, _, id)| *id);
+            while let Some((uri, res_type, id)) = created.pop() {
+                if let Some(lb) = lb.filter(|lb| *lb != id) {
+                    let _ = self.wait(lb).await;
+                }
+                if let Err(e) = self.delete(&uri, &res_type, id).await {
+                    eprintln!("Failed to delete {} {} - {}", res_type, id, e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Delete children before their parent. Failures are reported and the rest is still
+    /// deleted, so nothing is left orphaned by one failure.
+    async fn delete_stack(&mut self, stack: Stack) -> Result<(), Error> {
+        let id = |v: &Value| Uuid::parse_str(v["id"].as_str().unwrap_or_default()).ok();
+        let Some(lb) = id(&stack.loadbalancer) else {
+            return Err(anyhow!("No ID found in loadbalancer"));
+        };
+        let mut children = Vec::new();
+        for p in &stack.pools {
+            let Some(pool_id) = id(&p.pool) else { continue };
+            if let Some(hm) = p.healthmonitor.as_ref().and_then(id) {
+                children.push((self.uri("healthmonitor"), "healthmonitor", hm));
+            }
+            let member_uri = self.member_uri(&pool_id.to_string());
+            for m in p.members.iter().filter_map(id) {
+                children.push((member_uri.clone(), "member", m));
+            }
+            children.push((self.uri("pool"), "pool", pool_id));
+        }
+        for l in stack.listeners.iter().filter_map(id) {
+            children.push((self.uri("listener"), "listener", l));
+        }
+
+        let mut failed = 0;
+        for (uri, res_type, child) in children {
+            let result = match self.wait(lb).await {
+                Ok(()) => self.delete(&uri, res_type, child).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to delete {} {} - {}", res_type, child, e);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!(
+                "Failed to delete {} children of loadbalancer {}, it is kept",
+                failed,
+                lb
+            ));
+        }
+        self.wait(lb).await?;
+        self.delete(&self.uri("loadbalancer"), "loadbalancer", lb)
+            .await
+    }
+}
+
+/// Tree for table format, which is the default
+fn display(stacks: &[Stack], fmt: Option<&OutputFormat>) {
+    match fmt {
+        None | Some(OutputFormat::Table) => {
+            for stack in stacks {
+                println!("{}", stack.tree().lines().join("\n"));
+            }
+            note(format!("Total: {}", stacks.len()));
+        }
+        Some(fmt) => print_output(&json!(stacks), fmt, None),
+    }
+}
+
+pub async fn handle(
+    cfg: &Config,
+    oper: LbOperations,
+    fmt: Option<&OutputFormat>,
+) -> Result<(), Error> {
+    let mut lb = Lb::new(cfg)?;
+    match oper {
+        LbOperations::Create(opts) => {
+            let id = lb.create_stack(opts).await?;
+            display(&[lb.stack(id).await?], fmt);
+        }
+        LbOperations::Show { names } => {
+            let mut stacks = Vec::new();
+            for name in names {
+                let id = lb.resolve("loadbalancer", &name).await?;
+                stacks.push(lb.stack(id).await?);
+            }
+            display(&stacks, fmt);
+        }
+        LbOperations::List => {
+            let mut stacks = Vec::new();
+            for value in lb.read_all(&lb.uri("loadbalancer"), "loadbalancer").await? {
+                let id = Uuid::parse_str(value["id"].as_str().unwrap_or_default())?;
+                stacks.push(lb.stack(id).await?);
+            }
+            display(&stacks, fmt);
+        }
+        LbOperations::Delete { names } => {
+            let mut errors = Vec::new();
+            for name in names {
+                let result = async {
+                    let id = lb.resolve("loadbalancer", &name).await?;
+                    let stack = lb.stack(id).await?;
+                    lb.delete_stack(stack).await
+                }
+                .await;
+                if let Err(e) = result {
+                    eprintln!("Failed to delete loadbalancer {} - {}", name, e);
+                    errors.push(name);
+                }
+            }
+            if !errors.is_empty() {
+                return Err(anyhow!(
+                    "Failed to delete loadbalancer {}",
+                    errors.join(", ")
+                ));
+            }
+        }
+    }
+    note(format!("API IP: {}", lb.api.host));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_member() {
+        let member = |m| parse_member(m, 80).unwrap();
+        assert_eq!(member("10.0.0.10"), ("10.0.0.10".to_string(), 80));
+        assert_eq!(member("10.0.0.10:8080"), ("10.0.0.10".to_string(), 8080));
+        assert_eq!(member("fd00::10"), ("fd00::10".to_string(), 80));
+        assert_eq!(member("[fd00::10]"), ("fd00::10".to_string(), 80));
+        assert_eq!(member("[fd00::10]:8080"), ("fd00::10".to_string(), 8080));
+        assert_eq!(member("backend:8080"), ("backend".to_string(), 8080));
+        assert!(parse_member("10.0.0.10:http", 80).is_err());
+    }
+
+    #[test]
+    fn test_tree() {
+        let stack = Stack {
+            loadbalancer: json!({"id": "l1", "name": "lb", "vip_address": "10.0.0.5",
+                                 "provisioning_status": "ACTIVE"}),
+            listeners: vec![
+                json!({"id": "ls1", "name": "lb-listener", "protocol": "HTTP",
+                                   "protocol_port": 80, "default_pool_id": "p1"}),
+            ],
+            pools: vec![
+                PoolStack {
+                    pool: json!({"id": "p1", "name": "lb-pool", "protocol": "HTTP",
+                                 "lb_algorithm": "ROUND_ROBIN"}),
+                    members: vec![json!({"id": "m1", "address": "10.0.0.10", "protocol_port": 80})],
+                    healthmonitor: Some(json!({"id": "h1", "name": "lb-monitor", "type": "PING"})),
+                },
+                PoolStack {
+                    pool: json!({"id": "p2", "name": "spare", "protocol": "TCP",
+                                 "lb_algorithm": "SOURCE_IP"}),
+                    members: vec![],
+                    healthmonitor: None,
+                },
+            ],
+        };
+        assert_eq!(
+            stack.tree().lines(),
+            [
+                "loadbalancer lb (l1) vip 10.0.0.5 [ACTIVE]",
+                "├── listener lb-listener (ls1) HTTP:80",
+                "│   └── pool lb-pool (p1) HTTP ROUND_ROBIN",
+                "│       ├── member - (m1) 10.0.0.10:80",
+                "│       └── healthmonitor lb-monitor (h1) PING",
+                "└── pool spare (p2) TCP SOURCE_IP",
+            ]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use super::output::note;
+use comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::*;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// Samples of all requests sent by this process
+static SAMPLES: Mutex<Vec<Sample>> = Mutex::new(Vec::new());
+/// When the first request is sent, or stress starts
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Operation and resource type of a request
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub oper: String,
+    pub res_type: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub oper: String,
+    #[serde(rename = "type")]
+    pub res_type: String,
+    /// HTTP status, none if request failed without response
+    pub status: Option<u16>,
+    /// Latency in seconds
+    pub latency: f64,
+    /// Seconds since first request
+    pub start: f64,
+}
+
+/// Start of all samples, set by the first call
+pub fn start() -> Instant {
+    *START.get_or_init(Instant::now)
+}
+
+pub fn record(label: &Label, status: Option<u16>, begin: Instant, elapsed: Duration) {
+    let sample = Sample {
+        oper: label.oper.clone(),
+        res_type: label.res_type.clone(),
+        status,
+        latency: elapsed.as_secs_f64(),
+        start: begin.saturating_duration_since(start()).as_secs_f64(),
+    };
+    SAMPLES.lock().unwrap().push(sample);
+}
+
+pub fn samples() -> Vec<Sample> {
+    SAMPLES.lock().unwrap().clone()
+}
+
+/// Nearest-rank percentile of sorted samples
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn secs(d: Option<f64>) -> String {
+    match d {
+        Some(d) => format!("{:.3}", d),
+        None => String::from("-"),
+    }
+}
+
+/// Print latency and status summary for each operation and resource type
+pub fn report() {
+    let samples = samples();
+    if samples.is_empty() {
+        return;
+    }
+    let mut groups: BTreeMap<(&str, &str), Vec<&Sample>> = BTreeMap::new();
+    for s in &samples {
+        groups.entry((&s.oper, &s.res_type)).or_default().push(s);
+    }
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            "OPERATION",
+            "TYPE",
+            "COUNT",
+            "MIN",
+            "MEAN",
+            "P50",
+            "P90",
+            "P99",
+            "MAX",
+            "STATUS",
+        ]);
+    for ((oper, res_type), group) in groups {
+        let mut latency: Vec<f64> = group.iter().map(|s| s.latency).collect();
+        latency.sort_by(f64::total_cmp);
+        let mean = latency.iter().sum::<f64>() / latency.len() as f64;
+        let mut status: BTreeMap<String, usize> = BTreeMap::new();
+        for s in &group {
+            let code = s.status.map_or(String::from("ERR"), |c| c.to_string());
+            *status.entry(code).or_default() += 1;
+        }
+        let failed = group.iter().any(|s| !matches!(s.status, Some(200..=299)));
+        let status: Vec<_> = status.iter().map(|(c, n)| format!("{c}:{n}")).collect();
+        table.add_row(vec![
+            Cell::new(oper),
+            Cell::new(res_type),
+            Cell::new(latency.len()),
+            Cell::new(secs(latency.first().copied())),
+            Cell::new(secs(Some(mean))),
+            Cell::new(secs(percentile(&latency, 50.0))),
+            Cell::new(secs(percentile(&latency, 90.0))),
+            Cell::new(secs(percentile(&latency, 99.0))),
+            Cell::new(secs(latency.last().copied())),
+            Cell::new(status.join(" ")).fg(if failed { Color::Red } else { Color::Reset }),
+        ]);
+    }
+    let elapsed = start().elapsed().as_secs_f64();
+    note(table);
+    note(format!(
+        "Total: {} requests in {:.3}s, {:.2} req/s",
+        samples.len(),
+        elapsed,
+        samples.len() as f64 / elapsed
+    ));
+}
+
+/// Export raw samples as CSV or JSON according to file extension
+pub fn export(path: &Path) -> anyhow::Result<()> {
+    let samples = samples();
+    let data = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::to_string_pretty(&samples)?,
+        _ => {
+            let mut csv = String::from("operation,type,status,latency,start\n");
+            for s in &samples {
+                csv.push_str(&format!(
+                    "{},{},{},{:.6},{:.6}\n",
+                    s.oper,
+                    s.res_type,
+                    s.status.map_or(String::new(), |c| c.to_string()),
+                    s.latency,
+                    s.start
+                ));
+            }
+            csv
+        }
+    };
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let samples: Vec<_> = (1..=100).map(|i| i as f64).collect();
+        assert_eq!(percentile(&samples, 50.0), Some(50.0));
+        assert_eq!(percentile(&samples, 99.0), Some(99.0));
+        assert_eq!(percentile(&samples[..1], 90.0), Some(1.0));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+}
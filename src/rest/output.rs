@@ -1,50 +1,309 @@
 use comfy_table::modifiers::UTF8_ROUND_CORNERS;
 use comfy_table::presets::UTF8_FULL_CONDENSED;
 use comfy_table::*;
-use serde_json::Value;
+use super::path::{lookup, root};
+use serde_json::{Map, Value};
+use std::{cmp::Ordering, str::FromStr, sync::OnceLock};
+
+/// Set by `--quiet`, so that stdout has nothing but payload
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+pub fn set_quiet(quiet: bool) {
+    let _ = QUIET.set(quiet);
+}
+
+pub fn quiet() -> bool {
+    QUIET.get().copied().unwrap_or(false)
+}
+
+/// Set by `--wide`, tables of lists show all columns without truncation
+static WIDE: OnceLock<bool> = OnceLock::new();
+
+pub fn set_wide(wide: bool) {
+    let _ = WIDE.set(wide);
+}
+
+fn wide() -> bool {
+    WIDE.get().copied().unwrap_or(false)
+}
+
+/// Longest cell in a table of list unless it's wide
+const MAX_CELL: usize = 40;
+/// Number of columns picked for a list without `--field`
+const AUTO_COLUMNS: usize = 6;
+/// Fields coloured by value in a table
+const STATUS_FIELDS: [&str; 5] = [
+    "status",
+    "admin_state_up",
+    "provisioning_status",
+    "operating_status",
+    "enabled",
+];
+
+/// Print a line besides payload, like total or API host, which goes to stderr in quiet mode
+pub fn note(line: impl std::fmt::Display) {
+    if quiet() {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Format of output, `template=...` prints each item with `{{path}}` replaced by its value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    /// Compact JSON, one item per line
+    Text,
+    Yaml,
+    Csv,
+    Template(String),
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "text" => Ok(OutputFormat::Text),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => match s.strip_prefix("template=") {
+                Some(template) if !template.is_empty() => {
+                    Ok(OutputFormat::Template(template.to_string()))
+                }
+                _ => Err(format!(
+                    "unknown format `{s}`, use one of table, json, text, yaml, csv or template='{{{{name}}}} {{{{id}}}}'"
+                )),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Table => write!(f, "table"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Yaml => write!(f, "yaml"),
+            OutputFormat::Csv => write!(f, "csv"),
+            OutputFormat::Template(template) => write!(f, "template={template}"),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Formats for people, which end with a total line
+    pub fn has_total(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Table | OutputFormat::Json | OutputFormat::Text
+        )
+    }
+}
+
+/// Column from `--field`, a path to the value with an optional alias as header,
+/// example: `ip=fixed_ips[0].ip_address`
+pub struct Column {
+    pub header: String,
+    pub path: String,
+}
+
+impl Column {
+    pub fn parse(field: &str) -> Self {
+        match field.split_once('=') {
+            Some((header, path)) => Self {
+                header: header.to_string(),
+                path: path.to_string(),
+            },
+            None => Self {
+                header: field.to_string(),
+                path: field.to_string(),
+            },
+        }
+    }
+
+    /// Value of this column, missing one is empty
+    pub fn cell(&self, obj: &Value) -> String {
+        lookup(obj, &self.path).map_or(String::new(), |v| v.to_string())
+    }
+
+    /// Cell of a table row, truncated, coloured by status and highlighted if it's changed
+    fn table_cell(&self, obj: &Value, changed: bool) -> Cell {
+        let text = truncate(self.cell(obj));
+        let color = match STATUS_FIELDS.contains(&root(&self.path)) {
+            true if colored::control::SHOULD_COLORIZE.should_colorize() => status_color(&text),
+            _ => None,
+        };
+        let cell = match color {
+            Some(color) => Cell::new(text).fg(color),
+            None => Cell::new(text),
+        };
+        highlight(cell, changed)
+    }
+}
+
+fn highlight(cell: Cell, changed: bool) -> Cell {
+    match changed {
+        true => cell.add_attribute(Attribute::Reverse),
+        false => cell,
+    }
+}
+
+fn truncate(s: String) -> String {
+    if wide() || s.chars().count() <= MAX_CELL {
+        return s;
+    }
+    let mut s: String = s.chars().take(MAX_CELL - 1).collect();
+    s.push('…');
+    s
+}
+
+fn status_color(value: &str) -> Option<Color> {
+    match value.trim_matches('"').to_uppercase().as_str() {
+        "ACTIVE" | "UP" | "ONLINE" | "TRUE" => Some(Color::Green),
+        "ERROR" | "DOWN" | "OFFLINE" | "FALSE" => Some(Color::Red),
+        s if s.starts_with("PENDING") || s == "BUILD" || s == "DEGRADED" => Some(Color::Yellow),
+        _ => None,
+    }
+}
+
+/// Keys of all objects, in order they are found
+fn keys<'a>(items: impl IntoIterator<Item = &'a Value>) -> Vec<&'a String> {
+    let mut keys: Vec<&String> = Vec::new();
+    for obj in items.into_iter().filter_map(|item| item.as_object()) {
+        for key in obj.keys() {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    keys
+}
+
+fn key_columns(keys: Vec<&String>) -> Vec<Column> {
+    keys.into_iter()
+        .map(|key| Column {
+            header: key.clone(),
+            path: key.clone(),
+        })
+        .collect()
+}
+
+/// Columns of a list without `--field`: all keys for a wide table, otherwise
+/// ID, name and status first, then other plain values
+fn auto_columns(array: &[Value]) -> Vec<Column> {
+    let mut keys = keys(array);
+    if !wide() {
+        keys.retain(|k| {
+            array
+                .iter()
+                .filter_map(|v| v.get(k.as_str()))
+                .all(|v| !v.is_object() && !v.is_array())
+        });
+        keys.sort_by_key(|k| match k.as_str() {
+            "id" => 0,
+            "name" => 1,
+            k if STATUS_FIELDS.contains(&k) => 2,
+            _ => 3,
+        });
+        keys.truncate(AUTO_COLUMNS);
+    }
+    key_columns(keys)
+}
+
+/// Order of two values, numbers by value and missing ones last
+fn compare(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            _ => text(a).cmp(&text(b)),
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Sort items by value of path, or reverse them
+pub fn sort(items: &mut [Value], path: Option<&str>, reverse: bool) {
+    if let Some(path) = path {
+        items.sort_by(|a, b| compare(lookup(a, path), lookup(b, path)));
+    }
+    if reverse {
+        items.reverse();
+    }
+}
+
+/// Top level keys to be requested from server for fields
+pub fn field_roots(fields: &[String]) -> Vec<String> {
+    let mut roots: Vec<String> = Vec::new();
+    for field in fields {
+        let root = root(&Column::parse(field).path).to_string();
+        if !roots.contains(&root) {
+            roots.push(root);
+        }
+    }
+    roots
+}
+
+fn columns(fields: &[String]) -> Vec<Column> {
+    fields.iter().map(|f| Column::parse(f)).collect()
+}
 
 pub fn json_to_table(value: &Value, fields: Option<Vec<String>>) {
+    let len = print_table(value, fields);
+    if len != 0 {
+        note(format!("Total: {}", len));
+    }
+}
+
+/// Print table without total, return number of rows
+pub fn print_table(value: &Value, fields: Option<Vec<String>>) -> usize {
+    print_table_with(value, fields, |_, _| false)
+}
+
+/// Print table with cells highlighted by `changed(item, path)`, return number of rows
+pub fn print_table_with(
+    value: &Value,
+    fields: Option<Vec<String>>,
+    changed: impl Fn(&Value, &str) -> bool,
+) -> usize {
     let mut table = Table::new();
     let mut len = 0;
     table
         .load_preset(UTF8_FULL_CONDENSED)
         .apply_modifier(UTF8_ROUND_CORNERS)
-        .set_content_arrangement(ContentArrangement::Dynamic);
+        .set_content_arrangement(match wide() {
+            true => ContentArrangement::Disabled,
+            false => ContentArrangement::Dynamic,
+        });
     if value.is_array() {
         let array = value.as_array().unwrap();
         len = array.len();
         match fields {
-            Some(fields) => {
-                table.set_header(&fields);
-                for obj in array {
-                    let mut r = Vec::new();
-                    if let Some(dict) = obj.as_object() {
-                        fields.iter().for_each(|f| {
-                            if let Some(v) = dict.get(f) {
-                                r.push(Cell::new(&v.to_string()));
-                            }
-                        });
-                    }
-                    table.add_row(r);
+            // List of plain values, e.g. names from introspect
+            None if array.iter().all(|v| !v.is_object()) => {
+                for v in array {
+                    table.add_row(vec![text(v)]);
                 }
             }
-            None => {
+            fields => {
+                let columns = match fields {
+                    Some(fields) => columns(&fields),
+                    None => auto_columns(array),
+                };
+                table.set_header(columns.iter().map(|c| &c.header));
                 for obj in array {
-                    table.add_row(vec![
-                        Cell::new("KEY")
-                            .fg(Color::Green)
-                            .add_attribute(Attribute::Underlined)
-                            .set_alignment(CellAlignment::Center),
-                        Cell::new("VALUE")
-                            .fg(Color::Green)
-                            .add_attribute(Attribute::Underlined)
-                            .set_alignment(CellAlignment::Center),
-                    ]);
-                    if let Some(dict) = obj.as_object() {
-                        dict.iter().for_each(|(k, v)| {
-                            table.add_row(vec![k, &v.to_string()]);
-                        });
-                    }
+                    table.add_row(
+                        columns
+                            .iter()
+                            .map(|c| c.table_cell(obj, changed(obj, &c.path))),
+                    );
                 }
             }
         }
@@ -53,18 +312,21 @@ pub fn json_to_table(value: &Value, fields: Option<Vec<String>>) {
         let dict = value.as_object().unwrap();
         match fields {
             Some(fields) => {
-                table.set_header(&fields);
-                let mut r = Vec::new();
-                fields.iter().for_each(|f| {
-                    if let Some(v) = dict.get(f) {
-                        r.push(Cell::new(&v.to_string()));
-                    }
-                });
-                table.add_row(r);
+                let columns = columns(&fields);
+                table.set_header(columns.iter().map(|c| &c.header));
+                table.add_row(
+                    columns
+                        .iter()
+                        .map(|c| highlight(Cell::new(c.cell(value)), changed(value, &c.path))),
+                );
             }
             None => {
                 dict.iter().for_each(|(k, v)| {
-                    table.add_row(vec![k, &v.to_string()]);
+                    let changed = changed(value, k);
+                    table.add_row(vec![
+                        highlight(Cell::new(k), changed),
+                        highlight(Cell::new(v.to_string()), changed),
+                    ]);
                 });
             }
         }
@@ -72,18 +334,258 @@ pub fn json_to_table(value: &Value, fields: Option<Vec<String>>) {
 
     if len != 0 {
         println!("{table}");
-        println!("Total: {}", len);
     }
+    len
 }
 
-pub fn json_output(value: &Value, _fields: Option<Vec<String>>) {
-    let mut len = 0;
-    if value.is_array() {
-        let array = value.as_array().unwrap();
-        len = array.len();
-    } else if value.is_object() {
-        len = 1;
+/// Keep only fields of each object, keyed by header
+fn project(value: &Value, columns: &[Column]) -> Value {
+    match value {
+        Value::Array(array) => Value::Array(array.iter().map(|v| project(v, columns)).collect()),
+        Value::Object(_) => Value::Object(
+            columns
+                .iter()
+                .map(|c| (c.header.clone(), lookup(value, &c.path).cloned().unwrap_or_default()))
+                .collect::<Map<_, _>>(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// Node of tree view, rendered with box drawing characters
+pub struct Node {
+    pub label: String,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    fn render(&self, prefix: &str, lines: &mut Vec<String>) {
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i + 1 == self.children.len();
+            lines.push(format!(
+                "{prefix}{}{}",
+                if last { "└── " } else { "├── " },
+                child.label
+            ));
+            child.render(
+                &format!("{prefix}{}", if last { "    " } else { "│   " }),
+                lines,
+            );
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![self.label.clone()];
+        self.render("", &mut lines);
+        lines
+    }
+}
+
+pub fn json_output(value: &Value, fields: Option<Vec<String>>) {
+    let len = print_value(value, &OutputFormat::Json, fields, true);
+    note(format!("Total: {}", len));
+}
+
+/// Items of a list, or the value itself
+pub fn items(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(array) => array.iter().collect(),
+        Value::Null => Vec::new(),
+        _ => vec![value],
+    }
+}
+
+/// Plain text of a value, string without quotes and null as empty
+fn text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// CSV with a column for each field, or for each key found in items
+fn to_csv(value: &Value, fields: Option<Vec<String>>, header: bool) -> String {
+    let items = items(value);
+    let columns = match fields {
+        Some(fields) => columns(&fields),
+        None => key_columns(keys(items.iter().copied())),
+    };
+    let line = |cells: Vec<String>| {
+        let cells: Vec<_> = cells.iter().map(|c| csv_escape(c)).collect();
+        format!("{}\n", cells.join(","))
+    };
+    let mut csv = String::new();
+    if header && !columns.is_empty() {
+        csv += &line(columns.iter().map(|c| c.header.clone()).collect());
+    }
+    for item in items {
+        csv += &match columns.is_empty() {
+            true => line(vec![text(item)]),
+            false => line(
+                columns
+                    .iter()
+                    .map(|c| lookup(item, &c.path).map_or(String::new(), text))
+                    .collect(),
+            ),
+        };
+    }
+    csv
+}
+
+/// Replace each `{{path}}` in template with value of the item, a line per item
+fn to_template(value: &Value, template: &str) -> String {
+    let mut out = String::new();
+    for item in items(value) {
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            out += &rest[..start];
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("}}") else {
+                out += &rest[start..];
+                rest = "";
+                break;
+            };
+            out += &lookup(item, after[..end].trim()).map_or(String::new(), text);
+            rest = &after[end + 2..];
+        }
+        out += rest;
+        out.push('\n');
+    }
+    out
+}
+
+/// Print value in any format without total, return number of items.
+/// `header` is for CSV, which is left out on following pages.
+pub fn print_value(
+    value: &Value,
+    fmt: &OutputFormat,
+    fields: Option<Vec<String>>,
+    header: bool,
+) -> usize {
+    if *fmt == OutputFormat::Table {
+        return print_table(value, fields);
+    }
+    let projected;
+    let value = match (fmt, fields) {
+        (OutputFormat::Csv, fields) => {
+            print!("{}", to_csv(value, fields, header));
+            return items(value).len();
+        }
+        (OutputFormat::Template(template), _) => {
+            print!("{}", to_template(value, template));
+            return items(value).len();
+        }
+        (_, Some(fields)) => {
+            projected = project(value, &columns(&fields));
+            &projected
+        }
+        (_, None) => value,
+    };
+    match fmt {
+        OutputFormat::Text => items(value).iter().for_each(|item| println!("{item}")),
+        OutputFormat::Yaml => match serde_norway::to_string(value) {
+            Ok(yaml) => print!("{yaml}"),
+            Err(_) => println!("{value:#}"),
+        },
+        _ => println!("{value:#}"),
+    }
+    items(value).len()
+}
+
+/// Print value in format, with total for people
+pub fn print_output(value: &Value, fmt: &OutputFormat, fields: Option<Vec<String>>) {
+    match fmt {
+        OutputFormat::Table => json_to_table(value, fields),
+        OutputFormat::Json => json_output(value, fields),
+        fmt => {
+            let len = print_value(value, fmt, fields, true);
+            if fmt.has_total() {
+                note(format!("Total: {}", len));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_columns() {
+        let fields = vec![
+            String::from("name"),
+            String::from("ip=fixed_ips[0].ip_address"),
+        ];
+        let ports = json!([
+            {"name": "p1", "fixed_ips": [{"ip_address": "10.0.0.3"}]},
+            {"name": "p2", "fixed_ips": []},
+        ]);
+        let columns = columns(&fields);
+        assert_eq!(columns[1].header, "ip");
+        assert_eq!(columns[1].cell(&ports[1]), "");
+        assert_eq!(
+            project(&ports, &columns),
+            json!([{"name": "p1", "ip": "10.0.0.3"}, {"name": "p2", "ip": null}])
+        );
+        assert_eq!(field_roots(&fields), ["name", "fixed_ips"]);
+    }
+
+    #[test]
+    fn test_auto_columns() {
+        let mut ports = json!([
+            {"name": "p2", "id": "2", "status": "DOWN", "fixed_ips": [], "mtu": 9000},
+            {"name": "p1", "id": "1", "status": "ACTIVE", "description": "web", "mtu": 1500},
+            {"id": "3", "mtu": 1450},
+        ]);
+        let headers: Vec<_> = auto_columns(ports.as_array().unwrap())
+            .into_iter()
+            .map(|c| c.header)
+            .collect();
+        assert_eq!(headers, ["id", "name", "status", "mtu", "description"]);
+
+        let items = ports.as_array_mut().unwrap();
+        sort(items, Some("name"), false);
+        assert_eq!(items[0]["id"], "1");
+        assert_eq!(items[2]["id"], "3");
+        sort(items, Some("mtu"), true);
+        assert_eq!(items[0]["mtu"], 9000);
+        assert_eq!(truncate("x".repeat(50)).chars().count(), MAX_CELL);
+        assert_eq!(status_color("\"ACTIVE\""), Some(Color::Green));
+    }
+
+    #[test]
+    fn test_formats() {
+        let nets = json!([
+            {"name": "n1", "id": "1", "mtu": 1500},
+            {"name": "a,\"b\"", "id": "2", "shared": null},
+        ]);
+        assert_eq!(
+            to_csv(&nets, None, true),
+            "id,mtu,name,shared\n1,1500,n1,\n2,,\"a,\"\"b\"\"\",\n"
+        );
+        assert_eq!(
+            to_csv(&nets, Some(vec![String::from("ID=id")]), false),
+            "1\n2\n"
+        );
+        assert_eq!(
+            to_template(&nets, "{{ name }}: {{mtu}} {{"),
+            "n1: 1500 {{\na,\"b\":  {{\n"
+        );
+        assert_eq!(
+            "template={{id}}".parse(),
+            Ok(OutputFormat::Template(String::from("{{id}}")))
+        );
+        assert_eq!("yml".parse(), Ok(OutputFormat::Yaml));
+        assert!("xml".parse::<OutputFormat>().is_err());
     }
-    println!("{:#}", value);
-    println!("Total: {}", len);
 }
@@ -0,0 +1,55 @@
+use serde_json::Value;
+
+/// Look up a value by dotted path with array indices, e.g. `fixed_ips[0].ip_address`.
+/// A key which exists as a whole is taken first, so keys containing `.` still work.
+pub fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if let Some(v) = value.get(path) {
+        return Some(v);
+    }
+    let mut current = value;
+    for part in path.split('.') {
+        let (key, indices) = match part.find('[') {
+            Some(pos) => (&part[..pos], &part[pos..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            current = current.get(key)?;
+        }
+        for index in indices.split('[').skip(1) {
+            let index: usize = index.strip_suffix(']')?.parse().ok()?;
+            current = current.get(index)?;
+        }
+    }
+    Some(current)
+}
+
+/// Top level key of a path
+pub fn root(path: &str) -> &str {
+    path.split(['.', '[']).next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_lookup() {
+        let port = json!({
+            "fixed_ips": [{"ip_address": "10.0.0.3"}, {"ip_address": "10.0.0.4"}],
+            "binding:vif_details": {"port_filter": true},
+            "a.b": 1,
+        });
+        assert_eq!(
+            lookup(&port, "fixed_ips[1].ip_address"),
+            Some(&json!("10.0.0.4"))
+        );
+        assert_eq!(
+            lookup(&port, "binding:vif_details.port_filter"),
+            Some(&json!(true))
+        );
+        assert_eq!(lookup(&port, "a.b"), Some(&json!(1)));
+        assert_eq!(lookup(&port, "fixed_ips[2].ip_address"), None);
+        assert_eq!(root("fixed_ips[0].ip_address"), "fixed_ips");
+    }
+}
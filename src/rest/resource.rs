@@ -29,13 +29,11 @@ impl ResourceBuilder {
                     filters: Value::Object(Map::new()),
                     ..Data::default()
                 },
+                // `tenant_id`, `user_id` and `is_admin` are filled by `Rest` when it's sent
                 context: json!({
-                    "is_admin": true,
                     "operation": "READALL",
                     "request_id": format!("req-sdncli-{}", Uuid::new_v4()),
-                    "tenant_id": "ad88dd5d24ce4e2189a6ae7491c33e9d",
                     "type": "",
-                    "user_id": "44faef681cd34e1c80b8520dd6aebad4",
                 }),
             },
         }
@@ -60,6 +58,10 @@ impl ResourceBuilder {
         self
     }
 
+    pub fn get_id(&self) -> Option<Uuid> {
+        self.body.data.id
+    }
+
     pub fn fields(&mut self, fields: Vec<String>) -> &mut Self {
         self.body.data.fields = fields;
         self
@@ -1,74 +1,146 @@
 use crate::{
     config,
-    rest::output::{json_output, json_to_table},
+    rest::metrics::{self, Label},
+    rest::output::{self, note, print_output, OutputFormat},
     rest::resource::ResourceBuilder,
+    rest::token::{self, Token},
 };
 use anyhow::anyhow;
 use log::{debug, info};
-use reqwest::{self, Client, Method, RequestBuilder, Response};
-use serde_json::{self, json, Value};
+use reqwest::{self, Client, Method, RequestBuilder, Response, StatusCode};
+use serde_json::{self, json, Map, Value};
 use std::{
     collections::HashMap,
-    io::{self, Write},
+    io::{self, IsTerminal, Write},
+    sync::{Arc, Mutex},
     time::Instant,
 };
 use url::Url;
 use uuid::Uuid;
 
+/// Resources by URI and name
+type Names = HashMap<(String, String), Vec<Value>>;
+
+#[derive(Clone)]
 pub struct Rest {
     pub host: String,
     rest: config::Rest,
     auth: config::Auth,
     client: Client,
     token: String,
+    bench: bool,
+    /// Filled into context of request body, completed by token
+    context: config::Context,
+    /// Resources read by name, for names used more than once in a process. Clones share it.
+    names: Arc<Mutex<Names>>,
+}
+
+/// IDs are the same, with or without dashes
+pub fn same_id(value: &Value, id: &str) -> bool {
+    value
+        .as_str()
+        .is_some_and(|v| match (Uuid::parse_str(v), Uuid::parse_str(id)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => v == id,
+        })
+}
+
+/// Error status returned by server
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: StatusCode,
+    pub message: String,
+    pub body: String,
+    /// `request_id` in context of request body
+    pub request_id: Option<String>,
+}
+
+impl HttpError {
+    /// Error for scripts, API error body is kept as JSON if it is
+    pub fn to_json(&self) -> Value {
+        json!({
+            "status": self.status.as_u16(),
+            "message": self.message,
+            "body": serde_json::from_str::<Value>(&self.body).unwrap_or(json!(self.body)),
+            "request_id": self.request_id,
+        })
+    }
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n{}", self.message, self.body)
+    }
 }
 
+impl std::error::Error for HttpError {}
+
 pub trait RestBench {
-    async fn send_bench(self, bench: bool) -> anyhow::Result<Response>;
+    async fn send_bench(self, bench: bool, label: Label) -> anyhow::Result<Response>;
 }
 
 impl RestBench for RequestBuilder {
-    async fn send_bench(self, bench: bool) -> anyhow::Result<Response> {
+    async fn send_bench(self, bench: bool, label: Label) -> anyhow::Result<Response> {
+        // Samples are timed from before the first request, not after it finishes
+        metrics::start();
         let now = Instant::now();
-        let response = self.send().await?;
+        let response = match self.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                metrics::record(&label, None, now, now.elapsed());
+                return Err(e.into());
+            }
+        };
+        metrics::record(&label, Some(response.status().as_u16()), now, now.elapsed());
         if bench {
-            println!(
+            note(format!(
                 "time: {} [status: {} length: {:?}]",
                 now.elapsed().as_secs_f32(),
                 response.status(),
                 response.content_length(),
-            );
+            ));
         }
         debug!("{:#?}", response);
         match response.error_for_status_ref() {
             Ok(_) => Ok(response),
-            Err(e) => Err(anyhow!("{}\n{}", e, response.text().await?)),
+            Err(e) => Err(HttpError {
+                status: response.status(),
+                message: e.to_string(),
+                body: response.text().await?,
+                request_id: None,
+            }
+            .into()),
         }
     }
 }
 
 pub trait Output {
-    async fn output(self, fmt: &str, fields: Option<Vec<String>>) -> anyhow::Result<()>;
+    async fn output(self, fmt: &OutputFormat, fields: Option<Vec<String>>) -> anyhow::Result<()>;
 }
 
 impl Output for Response {
-    async fn output(self, fmt: &str, fields: Option<Vec<String>>) -> anyhow::Result<()> {
+    async fn output(self, fmt: &OutputFormat, fields: Option<Vec<String>>) -> anyhow::Result<()> {
         match self.content_length() {
             Some(0) => Ok(()),
-            _ => {
-                let text = self.text().await?;
-                debug!("Output Response: {}", text);
-                match serde_json::from_str::<Value>(&text) {
-                    Ok(json_value) if fmt == "table" => {
-                        json_to_table(&json_value, fields);
-                        Ok(())
-                    }
-                    Ok(json_value) => {
-                        json_output(&json_value, fields);
-                        Ok(())
-                    }
-                    Err(_) => Ok(println!("{}", text)),
-                }
+            _ => self.text().await?.output(fmt, fields).await,
+        }
+    }
+}
+
+impl Output for String {
+    async fn output(self, fmt: &OutputFormat, fields: Option<Vec<String>>) -> anyhow::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        debug!("Output Response: {}", self);
+        match serde_json::from_str::<Value>(&self) {
+            Ok(json_value) => {
+                print_output(&json_value, fmt, fields);
+                Ok(())
+            }
+            Err(_) => {
+                println!("{}", self);
+                Ok(())
             }
         }
     }
@@ -76,17 +148,15 @@ impl Output for Response {
 
 impl Rest {
     pub fn new(cfg: &config::Config) -> Self {
-        // Use auth host if rest host does not set
-        let host = match (&cfg.api.host, &cfg.auth.host) {
-            (Some(host), _) => host,
-            (_, host) => host,
-        };
         Self {
-            host: host.to_string(),
+            host: cfg.api_host().to_string(),
             rest: cfg.api.clone(),
             auth: cfg.auth.clone(),
             client: Client::new(),
             token: String::new(),
+            bench: true,
+            context: cfg.context(),
+            names: Arc::default(),
         }
     }
     // request token
@@ -98,19 +168,63 @@ impl Rest {
         self.rest.port = port;
     }
 
+    /// Print time and status for each request or not
+    pub fn set_bench(&mut self, bench: bool) {
+        self.bench = bench;
+    }
+
+    /// Request a token if we don't have one yet
+    pub async fn authenticate(&mut self) -> anyhow::Result<()> {
+        if self.token.is_empty() {
+            let token = get_auth(&self.auth).await?;
+            self.token = token.id;
+            self.context = self.context.clone().or(config::Context {
+                tenant_id: token.project_id,
+                user_id: token.user_id,
+                is_admin: None,
+            });
+        };
+        Ok(())
+    }
+
+    /// Tenant of request context, from command line, config or token
+    pub async fn tenant_id(&mut self) -> anyhow::Result<Option<String>> {
+        self.authenticate().await?;
+        Ok(self.context.tenant_id.clone())
+    }
+
+    /// Fill `tenant_id`, `user_id` and `is_admin` of request context, which are not in body
+    fn fill_context(&self, body: &mut Value) {
+        let Some(context) = body.get_mut("context").and_then(|c| c.as_object_mut()) else {
+            return;
+        };
+        let values = [
+            ("tenant_id", self.context.tenant_id.clone().map(Value::from)),
+            ("user_id", self.context.user_id.clone().map(Value::from)),
+            ("is_admin", Some(json!(self.context.is_admin.unwrap_or(true)))),
+        ];
+        for (key, value) in values {
+            if let Some(value) = value {
+                context.entry(key).or_insert(value);
+            }
+        }
+    }
+
     pub async fn request(
         &mut self,
         method: Method,
         uri: &str,
         body: Option<Value>,
     ) -> anyhow::Result<RequestBuilder> {
-        if self.token.is_empty() {
-            self.token = get_token(&self.auth).await?;
-        };
+        self.authenticate().await?;
+        let body = body.map(|mut body| {
+            self.fill_context(&mut body);
+            body
+        });
         let url = Url::parse(&format!("http://{}:{}/", self.host, self.rest.port))?.join(uri)?;
-        if body.is_some() {
+        if let Some(body) = &body {
             info!("curl -D - -s -X {} {} -H \"Content-Type:application/json\" -H \"X-Auth-Token:{}\" -d '{}'",
-                  method.as_str(), url, self.token, body.as_ref().unwrap());
+                  method.as_str(), url, self.token, body);
         } else {
             info!(
                 "curl -D - -s -X {} {} -H \"Content-Type:application/json\" -H \"X-Auth-Token:{}\"",
@@ -130,117 +244,411 @@ impl Rest {
         Ok(builder)
     }
 
-    pub async fn post(&mut self, uri: &str, body: Value) -> anyhow::Result<Response> {
-        self.request(reqwest::Method::POST, uri, Some(body))
+    /// Send request, and retry once with a new token if current one is rejected
+    pub async fn send(
+        &mut self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+        bench: bool,
+    ) -> anyhow::Result<Response> {
+        let label = label(&method, uri, body.as_ref());
+        let request_id = body
+            .as_ref()
+            .and_then(|b| b["context"]["request_id"].as_str())
+            .map(String::from);
+        let result = self
+            .request(method.clone(), uri, body.clone())
             .await?
-            .send_bench(true)
-            .await
+            .send_bench(bench, label.clone())
+            .await;
+        match result {
+            Err(e) if matches!(e.downcast_ref::<HttpError>(),
+                               Some(err) if err.status == StatusCode::UNAUTHORIZED) => {
+                info!("Token rejected, request a new one");
+                token::invalidate(&self.auth);
+                self.token.clear();
+                self.request(method, uri, body)
+                    .await?
+                    .send_bench(bench, label)
+                    .await
+            }
+            result => result,
+        }
+        .map_err(|e| match e.downcast::<HttpError>() {
+            Ok(mut err) => {
+                err.request_id = request_id;
+                err.into()
+            }
+            Err(e) => e,
+        })
+    }
+
+    pub async fn post(&mut self, uri: &str, body: Value) -> anyhow::Result<Response> {
+        self.send(reqwest::Method::POST, uri, Some(body), self.bench).await
     }
 
     pub async fn put(&mut self, uri: &str, body: Value) -> anyhow::Result<Response> {
-        self.request(reqwest::Method::PUT, uri, Some(body))
-            .await?
-            .send_bench(true)
-            .await
+        self.send(reqwest::Method::PUT, uri, Some(body), self.bench).await
     }
 
     pub async fn get(&mut self, uri: &str) -> anyhow::Result<Response> {
-        self.request(reqwest::Method::GET, uri, None)
-            .await?
-            .send_bench(true)
-            .await
+        self.send(reqwest::Method::GET, uri, None, self.bench).await
     }
 
     pub async fn delete(&mut self, uri: &str) -> anyhow::Result<Response> {
-        self.request(reqwest::Method::DELETE, uri, None)
-            .await?
-            .send_bench(true)
-            .await
+        self.send(reqwest::Method::DELETE, uri, None, self.bench).await
     }
 
+    /// READALL page by page with `limit` and `marker`, until a page is not full.
+    /// Return number of resources.
+    pub async fn read_all_pages(
+        &mut self,
+        uri: &str,
+        builder: &mut ResourceBuilder,
+        filters: Option<Value>,
+        page_size: usize,
+        mut on_page: impl FnMut(&[Value]),
+    ) -> anyhow::Result<usize> {
+        let mut filters = match filters {
+            Some(Value::Object(filters)) => filters,
+            _ => Map::new(),
+        };
+        filters.remove("marker");
+        filters.insert(String::from("limit"), json!(page_size));
+        let mut total = 0;
+        let mut head: Option<String> = None;
+        loop {
+            let body = builder.filters(Value::Object(filters.clone())).build()?;
+            let page: Value = self.post(uri, body).await?.json().await?;
+            let items = page.as_array().map(|a| &a[..]).unwrap_or_default();
+            let id = |i: Option<&Value>| i.and_then(|i| i["id"].as_str()).map(String::from);
+            // Server ignoring marker would return the first page forever
+            if head.is_some() && id(items.first()) == head {
+                return Err(anyhow!(
+                    "Same page is returned again, {} may not support marker",
+                    uri
+                ));
+            }
+            head = id(items.first());
+            total += items.len();
+            if !items.is_empty() {
+                on_page(items);
+            }
+            match id(items.last()) {
+                Some(marker) if items.len() >= page_size => {
+                    filters.insert(String::from("marker"), json!(marker));
+                }
+                _ => break,
+            }
+        }
+        Ok(total)
+    }
+
+    /// ID of a resource by name, a name matching several resources is asked about
     pub async fn name_to_id(&mut self, uri: &str, name: &str) -> anyhow::Result<Uuid> {
-        let body = ResourceBuilder::new()
-            .res_type(uri)
-            .filters(json!({"name": name}))
-            .build()?;
-        let response: Vec<HashMap<String, Value>> = self
-            .request(reqwest::Method::POST, uri, Some(body))
-            .await?
-            .send_bench(false)
-            .await?
-            .error_for_status()?
-            .json()
-            .await?;
-        debug!("FQ Name: {:#?}", response);
-        let wanted: Vec<HashMap<String, Value>> = response
-            .into_iter()
-            .filter(|res| res.get("name") == Some(json! {name}).as_ref())
-            .collect();
-        match wanted.len() {
-            0 => Err(anyhow!(
-                "{} {} Not Found",
-                uri.split('/').last().unwrap(),
-                name
-            )),
-            1 => Ok(Uuid::parse_str(wanted[0]["id"].as_str().unwrap())?),
-            _ => {
-                println!("@@ Found multiple {}:", name);
-                wanted
+        Ok(self.resolve_name(uri, name, Resolve::Ask).await?[0])
+    }
+
+    /// Resources of name by READALL filter, which may be qualified by fq_name like
+    /// `default-domain:admin:net1`. Several matches are resolved by `resolve`.
+    pub async fn resolve_name(
+        &mut self,
+        uri: &str,
+        name: &str,
+        resolve: Resolve,
+    ) -> anyhow::Result<Vec<Uuid>> {
+        let res_type = uri.split('/').next_back().unwrap_or_default();
+        let mut wanted = candidates(self.named(uri, name).await?, name);
+        if wanted.is_empty()
+            && let Some((_, last)) = name.rsplit_once(':')
+        {
+            wanted = candidates(self.named(uri, last).await?, name);
+        }
+        let wanted = narrow(wanted, config::cli_tenant(), resolve);
+        let ids = wanted
+            .iter()
+            .map(|res| Uuid::parse_str(res["id"].as_str().unwrap_or_default()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match (ids.len(), resolve) {
+            (0, _) => Err(anyhow!("{} {} Not Found", res_type, name)),
+            (1, _) | (_, Resolve::All) => Ok(ids),
+            (_, Resolve::First) => Ok(vec![ids[0]]),
+            (_, Resolve::Ask) if !io::stdin().is_terminal() || output::quiet() => {
+                let list: Vec<_> = wanted
                     .iter()
-                    .enumerate()
-                    .for_each(|(i, res)| println!("{} = {}:{}", i, res["id"], res["fq_name"]));
-                print!("Please select: ");
-                io::stdout().flush().unwrap();
+                    .map(|res| {
+                        format!(
+                            "  {} {}",
+                            res["id"].as_str().unwrap_or_default(),
+                            fq_name(res).join(":")
+                        )
+                    })
+                    .collect();
+                Err(anyhow!(
+                    "{} {} matches {} resources, qualify it by fq_name, or use --first or --all:\n{}",
+                    res_type,
+                    name,
+                    ids.len(),
+                    list.join("\n")
+                ))
+            }
+            (_, Resolve::Ask) => {
+                eprintln!("@@ Found multiple {}:", name);
+                wanted.iter().enumerate().for_each(|(i, res)| {
+                    eprintln!("{} = {}:{}", i, res["id"], fq_name(res).join(":"))
+                });
+                eprint!("Please select: ");
+                io::stderr().flush()?;
 
                 // Get index from stdin user input
                 let mut number = String::new();
                 io::stdin().read_line(&mut number)?;
                 let index = number.trim().parse::<usize>()?;
-                if index >= wanted.len() {
+                if index >= ids.len() {
                     Err(anyhow!(
                         "Your select {} is not in range 0-{}",
                         index,
-                        wanted.len() - 1
+                        ids.len() - 1
                     ))
                 } else {
-                    Ok(Uuid::parse_str(wanted[index]["id"].as_str().unwrap())?)
+                    Ok(vec![ids[index]])
+                }
+            }
+        }
+    }
+
+    /// IDs of names in order, names not cached yet are read by one READALL with all of them.
+    /// A name matching several resources gives several IDs with `Resolve::All`.
+    pub async fn resolve_names(
+        &mut self,
+        uri: &str,
+        names: &[String],
+        resolve: Resolve,
+    ) -> anyhow::Result<Vec<(String, Uuid)>> {
+        let mut unknown: Vec<&str> = Vec::new();
+        for name in names.iter().filter(|n| Uuid::parse_str(n).is_err()) {
+            // Last part of a qualified name may be needed too
+            let last = name.rsplit_once(':').map(|(_, last)| last);
+            for n in std::iter::once(name.as_str()).chain(last) {
+                let key = (uri.to_string(), n.to_string());
+                if !self.names.lock().unwrap().contains_key(&key) && !unknown.contains(&n) {
+                    unknown.push(n);
+                }
+            }
+        }
+        if !unknown.is_empty() {
+            let items = self.read_named(uri, &unknown).await?;
+            let mut cache = self.names.lock().unwrap();
+            for n in unknown {
+                let matched = items
+                    .iter()
+                    .filter(|res| res["name"].as_str() == Some(n))
+                    .cloned()
+                    .collect();
+                cache.insert((uri.to_string(), n.to_string()), matched);
+            }
+        }
+
+        let mut resolved = Vec::new();
+        for name in names {
+            match Uuid::parse_str(name) {
+                Ok(id) => resolved.push((name.clone(), id)),
+                Err(_) => {
+                    for id in self.resolve_name(uri, name, resolve).await? {
+                        resolved.push((name.clone(), id));
+                    }
                 }
             }
         }
+        Ok(resolved)
+    }
+
+    /// Resources of a name, from cache or by READALL
+    async fn named(&mut self, uri: &str, name: &str) -> anyhow::Result<Vec<Value>> {
+        let key = (uri.to_string(), name.to_string());
+        if let Some(items) = self.names.lock().unwrap().get(&key) {
+            return Ok(items.clone());
+        }
+        let items = self.read_named(uri, &[name]).await?;
+        self.names.lock().unwrap().insert(key, items.clone());
+        Ok(items)
+    }
+
+    async fn read_named(&mut self, uri: &str, names: &[&str]) -> anyhow::Result<Vec<Value>> {
+        let res_type = uri.split('/').next_back().unwrap_or_default();
+        let body = ResourceBuilder::new()
+            .res_type(res_type)
+            .filters(json!({ "name": names }))
+            .build()?;
+        let response: Vec<Value> = self
+            .send(reqwest::Method::POST, uri, Some(body), false)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        debug!("FQ Name: {:#?}", response);
+        Ok(response)
+    }
+}
+
+/// How a name matching several resources is resolved
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Resolve {
+    /// Ask on terminal, otherwise fail with candidates
+    #[default]
+    Ask,
+    First,
+    All,
+}
+
+/// fq_name as a list, which may be returned as a string joined by `:`
+fn fq_name(res: &Value) -> Vec<String> {
+    match &res["fq_name"] {
+        Value::Array(a) => a
+            .iter()
+            .map(|s| s.as_str().unwrap_or_default().to_string())
+            .collect(),
+        Value::String(s) => s.split(':').map(String::from).collect(),
+        _ => Vec::new(),
     }
 }
 
+/// Resources of name, or whose fq_name ends with parts of a qualified name
+fn candidates(items: Vec<Value>, name: &str) -> Vec<Value> {
+    let parts: Vec<_> = name.split(':').collect();
+    items
+        .into_iter()
+        .filter(|res| {
+            res["name"].as_str() == Some(name) || {
+                let fq = fq_name(res);
+                parts.len() > 1 && fq.len() >= parts.len() && fq[fq.len() - parts.len()..] == parts
+            }
+        })
+        .collect()
+}
+
+/// Resources of tenant given by `--tenant` are taken, if there are some. Tenant of config or
+/// token doesn't narrow them, neither does `--all`.
+fn narrow(wanted: Vec<Value>, tenant: Option<&str>, resolve: Resolve) -> Vec<Value> {
+    let Some(tenant) = tenant.filter(|_| wanted.len() > 1 && resolve != Resolve::All) else {
+        return wanted;
+    };
+    let own: Vec<_> = wanted
+        .iter()
+        .filter(|res| same_id(&res["tenant_id"], tenant))
+        .cloned()
+        .collect();
+    if own.is_empty() { wanted } else { own }
+}
+
+/// Operation and resource type from request context, or from method and URI
+fn label(method: &Method, uri: &str, body: Option<&Value>) -> Label {
+    let context = body.map(|b| &b["context"]);
+    let oper = context.and_then(|c| c["operation"].as_str());
+    let res_type = context.and_then(|c| c["type"].as_str()).unwrap_or_else(|| {
+        // Last part of path which is not an ID
+        uri.split('?')
+            .next()
+            .unwrap_or_default()
+            .split('/')
+            .rfind(|s| !s.is_empty() && Uuid::parse_str(s).is_err())
+            .unwrap_or(uri)
+    });
+    Label {
+        oper: oper.unwrap_or(method.as_str()).to_string(),
+        res_type: res_type.to_string(),
+    }
+}
+
+/// Get token from cache, or request a new one from Keystone
 pub async fn get_token(cfg: &config::Auth) -> anyhow::Result<String> {
-    let version = cfg.version.to_lowercase();
-    let (uri, body) = match &version as &str {
-        "v3" => (
-            format!("http://{}:{}/v3/auth/tokens", cfg.host, cfg.port),
-            json!({
+    Ok(get_auth(cfg).await?.id)
+}
+
+/// Token with project and user it belongs to
+pub async fn get_auth(cfg: &config::Auth) -> anyhow::Result<Token> {
+    if let Some(token) = token::cached(cfg) {
+        return Ok(token);
+    }
+    let token = request_token(cfg).await?;
+    token::store(cfg, &token);
+    Ok(token)
+}
+
+/// Keystone v3 auth body, scoped to project if it is configured
+fn v3_auth(cfg: &config::Auth) -> anyhow::Result<Value> {
+    match (
+        &cfg.application_credential_id,
+        &cfg.application_credential_secret,
+    ) {
+        (Some(id), Some(secret)) => {
+            // Application credential is always scoped to the project it belongs to
+            return Ok(json!({
                 "auth": {
                     "identity": {
-                        "methods":["password"],
-                        "password": {
-                            "user": {
-                                "name": cfg.user,
-                                "password": cfg.password,
-                                "domain": { "name": "Default" }
-                            }
-                        }
+                        "methods": ["application_credential"],
+                        "application_credential": { "id": id, "secret": secret }
                     }
                 }
-            }),
+            }));
+        }
+        (None, None) => (),
+        _ => {
+            return Err(anyhow!(
+                "auth.application_credential_id and secret should be set together"
+            ))
+        }
+    }
+    let mut auth = json!({
+        "identity": {
+            "methods":["password"],
+            "password": {
+                "user": {
+                    "name": cfg.user,
+                    "password": cfg.password,
+                    "domain": { "name": cfg.user_domain.as_deref().unwrap_or("Default") }
+                }
+            }
+        }
+    });
+    if let Some(id) = &cfg.project_id {
+        auth["scope"] = json!({ "project": { "id": id } });
+    } else if !cfg.project.is_empty() {
+        auth["scope"] = json!({
+            "project": {
+                "name": cfg.project,
+                "domain": { "name": cfg.project_domain.as_deref().unwrap_or("Default") }
+            }
+        });
+    }
+    Ok(json!({ "auth": auth }))
+}
+
+fn v2_auth(cfg: &config::Auth) -> Value {
+    let mut auth = json!({
+        "passwordCredentials": {
+            "username": cfg.user,
+            "password": cfg.password,
+        }
+    });
+    match &cfg.project_id {
+        Some(id) => auth["tenantId"] = json!(id),
+        None => auth["tenantName"] = json!(cfg.project),
+    }
+    json!({ "auth": auth })
+}
+
+async fn request_token(cfg: &config::Auth) -> anyhow::Result<Token> {
+    let version = cfg.version.to_lowercase();
+    let (uri, body) = match &version as &str {
+        "v3" => (
+            format!("http://{}:{}/v3/auth/tokens", cfg.host, cfg.port),
+            v3_auth(cfg)?,
         ),
         _ => (
             format!("http://{}:{}/v2.0/tokens", cfg.host, cfg.port),
-            json!({
-                "auth": {
-                    "tenantName": cfg.project,
-                    "passwordCredentials": {
-                        "username": cfg.user,
-                        "password": cfg.password,
-                    }
-                }
-            }),
+            v2_auth(cfg),
         ),
     };
     info!(
@@ -254,25 +662,44 @@ pub async fn get_token(cfg: &config::Auth) -> anyhow::Result<String> {
         .await?
         .error_for_status()?;
     debug!("{:#?}", response);
-    match &version as &str {
-        "v3" => Ok(response.headers()["x-subject-token"]
-            .to_str()
-            .unwrap()
-            .to_owned()),
+    let subject = response
+        .headers()
+        .get("x-subject-token")
+        .and_then(|h| h.to_str().ok())
+        .map(String::from);
+    let body: Value = response.json().await?;
+    parse_token(&version, subject.as_deref(), &body)
+}
+
+/// Token from Keystone response, v3 token ID is in header `X-Subject-Token`
+fn parse_token(version: &str, subject: Option<&str>, body: &Value) -> anyhow::Result<Token> {
+    let id = |v: &Value| v.as_str().map(String::from);
+    match version {
+        "v3" => {
+            let token = &body["token"];
+            let subject = subject.ok_or_else(|| anyhow!("No X-Subject-Token in response"))?;
+            let mut t = Token::new(subject, token["expires_at"].as_str());
+            t.project_id = id(&token["project"]["id"]);
+            t.user_id = id(&token["user"]["id"]);
+            Ok(t)
+        }
         _ => {
-            let token: Value = response.json().await?;
-            Ok(token["access"]["token"]["id"].as_str().unwrap().to_owned())
+            let token = &body["access"]["token"];
+            let subject = token["id"]
+                .as_str()
+                .ok_or_else(|| anyhow!("No token ID in response"))?;
+            let mut t = Token::new(subject, token["expires"].as_str());
+            t.project_id = id(&token["tenant"]["id"]);
+            t.user_id = id(&body["access"]["user"]["id"]);
+            Ok(t)
         }
     }
 }
 
 pub async fn reschedule_vgws(cfg: &config::Config) -> anyhow::Result<()> {
-    let mut api = Rest::new(&cfg);
-    let vgws: Value = api.request(reqwest::Method::GET, "/vgws", None)
-        .await?
-        .send()
+    let mut api = Rest::new(cfg);
+    let vgws: Value = api.send(reqwest::Method::GET, "/vgws", None, false)
         .await?
-        .error_for_status()?
         .json()
         .await?;
     if !vgws.is_object() {
@@ -282,23 +709,19 @@ pub async fn reschedule_vgws(cfg: &config::Config) -> anyhow::Result<()> {
         for vgw in vgws["vgws"].as_array().unwrap() {
             let id = vgw["uuid"].as_str().unwrap();
             show_vgw(&mut api, &format!("/vgw/{id}")).await?;
-            println!("Reschedule vgw: {id}");
-            api.request(reqwest::Method::PUT, &format!("/vgw/{id}"), Some(body.clone()))
-                .await?
-                .send()
-                .await?
-                .error_for_status()?;
+            note(format!("Reschedule vgw: {id}"));
+            let url = format!("/vgw/{id}");
+            api.send(reqwest::Method::PUT, &url, Some(body.clone()), false)
+                .await?;
         }
+        metrics::report();
         Ok(())
     }
 }
 
 async fn show_vgw(api: &mut Rest, url: &str) -> anyhow::Result<()> {
-    let vgw: Value = api.request(reqwest::Method::GET, url, None)
-        .await?
-        .send()
+    let vgw: Value = api.send(reqwest::Method::GET, url, None, false)
         .await?
-        .error_for_status()?
         .json()
         .await?;
     match vgw["vgw"].get("virtual_router_refs") {
@@ -314,6 +737,140 @@ async fn show_vgw(api: &mut Rest, url: &str) -> anyhow::Result<()> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_candidates() {
+        let items = vec![
+            json!({"id": "1", "name": "net1", "fq_name": ["default-domain", "admin", "net1"]}),
+            json!({"id": "2", "name": "net1", "fq_name": "default-domain:demo:net1"}),
+            json!({"id": "3", "name": "net10", "fq_name": ["default-domain", "admin", "net10"]}),
+        ];
+        let ids = |name: &str| -> Vec<_> {
+            candidates(items.clone(), name)
+                .iter()
+                .map(|res| res["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(ids("net1"), ["1", "2"]);
+        assert_eq!(ids("demo:net1"), ["2"]);
+        assert_eq!(ids("default-domain:admin:net1"), ["1"]);
+        assert!(ids("other:admin:net1").is_empty());
+        assert!(ids("net").is_empty());
+    }
+
+    #[test]
+    fn test_narrow() {
+        let items = vec![
+            json!({"id": "1", "tenant_id": "b00c06f6d02e44939073b566f409b496"}),
+            json!({"id": "2", "tenant_id": "e82dedfaad3f46168cd373baf2bdb4ee"}),
+        ];
+        let ids = |tenant, resolve| -> Vec<_> {
+            narrow(items.clone(), tenant, resolve)
+                .iter()
+                .map(|res| res["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let tenant = Some("b00c06f6-d02e-4493-9073-b566f409b496");
+        assert_eq!(ids(tenant, Resolve::Ask), ["1"]);
+        assert_eq!(ids(tenant, Resolve::First), ["1"]);
+        assert_eq!(ids(tenant, Resolve::All), ["1", "2"]);
+        assert_eq!(ids(None, Resolve::Ask), ["1", "2"]);
+        assert_eq!(ids(Some("other"), Resolve::Ask), ["1", "2"]);
+    }
+
+    #[test]
+    fn test_parse_token() {
+        let v3 = json!({"token": {
+            "expires_at": "2015-02-11T20:42:33.000000Z",
+            "project": {"id": "p3", "name": "demo"},
+            "user": {"id": "u3", "name": "admin"},
+        }});
+        let token = parse_token("v3", Some("t3"), &v3).unwrap();
+        assert_eq!(token.id, "t3");
+        assert_eq!(token.project_id.as_deref(), Some("p3"));
+        assert_eq!(token.user_id.as_deref(), Some("u3"));
+        assert!(parse_token("v3", None, &v3).is_err());
+
+        let v2 = json!({"access": {
+            "token": {"id": "t2", "expires": "2015-02-11T20:42:33Z", "tenant": {"id": "p2"}},
+            "user": {"id": "u2"},
+        }});
+        let token = parse_token("v2", None, &v2).unwrap();
+        assert_eq!(token.id, "t2");
+        assert_eq!(token.project_id.as_deref(), Some("p2"));
+        assert_eq!(token.user_id.as_deref(), Some("u2"));
+    }
+
+    #[test]
+    fn test_context_precedence() {
+        let context = config::Context {
+            tenant_id: Some(String::from("cli")),
+            ..Default::default()
+        }
+        .or(config::Context {
+            tenant_id: Some(String::from("p2")),
+            user_id: Some(String::from("u2")),
+            is_admin: Some(false),
+        });
+        assert_eq!(context.tenant_id.as_deref(), Some("cli"));
+        assert_eq!(context.user_id.as_deref(), Some("u2"));
+        assert_eq!(context.is_admin, Some(false));
+    }
+
+    #[test]
+    fn test_http_error() {
+        let err = HttpError {
+            status: StatusCode::NOT_FOUND,
+            message: String::from("HTTP status client error (404 Not Found)"),
+            body: String::from(r#"{"NeutronError": {"type": "NetworkNotFound"}}"#),
+            request_id: Some(String::from("req-sdncli-1")),
+        };
+        assert_eq!(
+            err.to_json(),
+            json!({
+                "status": 404,
+                "message": "HTTP status client error (404 Not Found)",
+                "body": {"NeutronError": {"type": "NetworkNotFound"}},
+                "request_id": "req-sdncli-1",
+            })
+        );
+        let err = HttpError {
+            body: String::from("Bad Gateway"),
+            ..err
+        };
+        assert_eq!(err.to_json()["body"], "Bad Gateway");
+    }
+
+    #[test]
+    fn test_v3_auth() {
+        let mut auth = config::Auth {
+            host: String::from("127.0.0.1"),
+            port: 5000,
+            user: String::from("admin"),
+            password: String::from("secret"),
+            project: String::from("demo"),
+            version: String::from("v3"),
+            project_id: None,
+            user_domain: Some(String::from("lab")),
+            project_domain: None,
+            application_credential_id: None,
+            application_credential_secret: None,
+        };
+        let body = v3_auth(&auth).unwrap();
+        assert_eq!(body["auth"]["identity"]["password"]["user"]["domain"]["name"], "lab");
+        assert_eq!(body["auth"]["scope"]["project"]["name"], "demo");
+        assert_eq!(body["auth"]["scope"]["project"]["domain"]["name"], "Default");
+
+        auth.project_id = Some(String::from("p1"));
+        assert_eq!(v3_auth(&auth).unwrap()["auth"]["scope"], json!({"project": {"id": "p1"}}));
+
+        auth.application_credential_id = Some(String::from("c1"));
+        assert!(v3_auth(&auth).is_err());
+        auth.application_credential_secret = Some(String::from("s1"));
+        let body = v3_auth(&auth).unwrap();
+        assert_eq!(body["auth"]["identity"]["methods"], json!(["application_credential"]));
+        assert!(body["auth"].get("scope").is_none());
+    }
+
     #[tokio::test]
     async fn test_rest() -> Result<(), anyhow::Error> {
         let cfg = config::read_config()?;
@@ -0,0 +1,154 @@
+use crate::config;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io::Write, path::Path};
+
+/// Refresh token a little before it expires
+const EXPIRY_MARGIN: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub id: String,
+    /// UNIX timestamp
+    pub expires: Option<i64>,
+    /// Project the token is scoped to
+    pub project_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+impl Token {
+    pub fn new(id: &str, expires: Option<&str>) -> Self {
+        // Keystone v2 and v3 both return ISO 8601 in UTC, with or without fraction
+        let expires = expires.and_then(|e| {
+            DateTime::parse_from_rfc3339(e)
+                .map(|t| t.with_timezone(&Utc))
+                .or_else(|_| {
+                    e.trim_end_matches('Z')
+                        .parse()
+                        .map(|t: NaiveDateTime| t.and_utc())
+                })
+                .map(|t| t.timestamp())
+                .ok()
+        });
+        Self {
+            id: id.to_string(),
+            expires,
+            project_id: None,
+            user_id: None,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self.expires {
+            Some(expires) => expires - EXPIRY_MARGIN > Utc::now().timestamp(),
+            None => false,
+        }
+    }
+}
+
+fn key(cfg: &config::Auth) -> String {
+    format!(
+        "{}:{}/{}@{}/{}@{}/{}/{}",
+        cfg.host,
+        cfg.port,
+        cfg.user,
+        cfg.user_domain.as_deref().unwrap_or_default(),
+        cfg.project_id.as_deref().unwrap_or(&cfg.project),
+        cfg.project_domain.as_deref().unwrap_or_default(),
+        cfg.application_credential_id.as_deref().unwrap_or_default(),
+        cfg.version
+    )
+}
+
+fn load() -> HashMap<String, Token> {
+    config::token_file()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Write to a temporary file then rename it, so that readers never see a partial cache
+fn save(path: &Path, tokens: &HashMap<String, Token>) -> anyhow::Result<()> {
+    let tmp = path.with_extension(format!("json.{}", std::process::id()));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&tmp)?
+        .write_all(serde_json::to_string_pretty(tokens)?.as_bytes())?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Load, change and save the cache, other invocations wait for the lock meanwhile.
+/// The cache is saved if `change` returns true.
+fn update(change: impl FnOnce(&mut HashMap<String, Token>) -> bool) {
+    let update = || -> anyhow::Result<()> {
+        let path = config::token_file()?;
+        let lock = fs::File::create(path.with_extension("lock"))?;
+        // Released when the file is closed
+        lock.lock()?;
+        let mut tokens = load();
+        if change(&mut tokens) {
+            save(&path, &tokens)?;
+        }
+        Ok(())
+    };
+    if let Err(e) = update() {
+        warn!("Failed to save token cache - {}", e);
+    }
+}
+
+/// Token cached by previous invocations if it's not expired
+pub fn cached(cfg: &config::Auth) -> Option<Token> {
+    let token = load().remove(&key(cfg))?;
+    debug!("Cached token expires at {:?}", token.expires);
+    token.is_valid().then_some(token)
+}
+
+pub fn store(cfg: &config::Auth, token: &Token) {
+    update(|tokens| {
+        tokens.retain(|_, t| t.is_valid());
+        if token.expires.is_some() {
+            tokens.insert(key(cfg), token.clone());
+        }
+        true
+    });
+}
+
+/// Forget a token rejected by server
+pub fn invalidate(cfg: &config::Auth) {
+    update(|tokens| tokens.remove(&key(cfg)).is_some());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_expires() {
+        let v2 = Token::new("a", Some("2015-02-11T20:42:33Z"));
+        let v3 = Token::new("b", Some("2015-02-11T20:42:33.000000Z"));
+        assert_eq!(v2.expires, v3.expires);
+        assert!(v2.expires.is_some());
+        assert!(!v2.is_valid());
+        let future = (Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+        assert!(Token::new("c", Some(&future)).is_valid());
+        assert!(!Token::new("d", None).is_valid());
+    }
+
+    #[test]
+    fn test_token_cached() {
+        // Unscoped token without project is reused as long as it's not expired
+        let future = (Utc::now() + chrono::Duration::hours(1)).timestamp();
+        let token: Token = serde_json::from_value(serde_json::json!({
+            "id": "a", "expires": future, "user_id": "u1"
+        }))
+        .unwrap();
+        assert!(token.project_id.is_none());
+        assert!(token.is_valid());
+    }
+}
@@ -0,0 +1,329 @@
+//! `topology` command, which follows reference fields of a resource by READ and prints
+//! what it finds as a tree, or as dot for Graphviz.
+use super::output::Node;
+use crate::{
+    cli::{TopologyFormat, TopologyOpts},
+    config::{self, Config},
+    rest::resource::ResourceBuilder,
+    rest::rest::{HttpError, Rest},
+};
+use anyhow::{anyhow, Error};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use uuid::Uuid;
+
+/// Fields referring to another resource, and its type
+const REFERENCES: [(&str, &str); 14] = [
+    ("network_id", "network"),
+    ("subnet_id", "subnet"),
+    ("router_id", "router"),
+    ("port_id", "port"),
+    ("security_groups", "security_group"),
+    ("pool_id", "pool"),
+    ("subnets", "subnet"),
+    ("floating_network_id", "network"),
+    ("vip_subnet_id", "subnet"),
+    ("vip_port_id", "port"),
+    ("loadbalancer_id", "loadbalancer"),
+    ("listener_id", "listener"),
+    ("default_pool_id", "pool"),
+    ("healthmonitor_id", "healthmonitor"),
+];
+
+/// Type of `device_id` by `device_owner` of port, VMs and DHCP are not known here
+fn device_type(owner: &str) -> Option<&'static str> {
+    match owner {
+        o if o.starts_with("network:router") => Some("router"),
+        "network:floatingip" => Some("floatingip"),
+        o if o.starts_with("neutron:LOADBALANCER") => Some("loadbalancer"),
+        _ => None,
+    }
+}
+
+/// IDs in a string or a list of strings
+fn ids(value: &Value) -> Vec<Uuid> {
+    match value {
+        Value::String(s) => Uuid::parse_str(s).into_iter().collect(),
+        Value::Array(a) => a.iter().flat_map(ids).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// References of a resource as (field, type, ID), found in nested objects and lists too
+pub fn references(value: &Value) -> Vec<(String, &'static str, Uuid)> {
+    let mut refs = Vec::new();
+    match value {
+        Value::Object(obj) => {
+            for (key, v) in obj {
+                let res_type = match key.as_str() {
+                    "device_id" => obj["device_owner"].as_str().and_then(device_type),
+                    key => REFERENCES.iter().find(|(k, _)| *k == key).map(|(_, t)| *t),
+                };
+                match res_type {
+                    Some(res_type) => {
+                        refs.extend(ids(v).into_iter().map(|id| (key.clone(), res_type, id)))
+                    }
+                    None => refs.extend(references(v)),
+                }
+            }
+        }
+        Value::Array(a) => refs.extend(a.iter().flat_map(references)),
+        _ => (),
+    }
+    refs.dedup();
+    refs
+}
+
+/// Resource in graph, value is null if it can't be read
+struct Vertex {
+    res_type: String,
+    id: Uuid,
+    value: Value,
+    error: Option<String>,
+}
+
+impl Vertex {
+    fn label(&self) -> String {
+        let name = self.value["name"].as_str().filter(|n| !n.is_empty());
+        let mut label = format!("{} {} ({})", self.res_type, name.unwrap_or("-"), self.id);
+        let status = self.value["status"]
+            .as_str()
+            .or(self.value["provisioning_status"].as_str());
+        if let Some(status) = status {
+            label.push_str(&format!(" [{status}]"));
+        }
+        if let Some(error) = &self.error {
+            label.push_str(&format!(" <{error}>"));
+        }
+        label
+    }
+}
+
+#[derive(Default)]
+pub struct Graph {
+    vertices: Vec<Vertex>,
+    /// From, to and the field
+    edges: Vec<(usize, usize, String)>,
+}
+
+impl Graph {
+    fn node(&self, index: usize, field: Option<&str>, seen: &mut HashSet<usize>) -> Node {
+        let mut label = self.vertices[index].label();
+        if let Some(field) = field {
+            label = format!("{field} → {label}");
+        }
+        // A resource is expanded only once, so that a cycle ends
+        if !seen.insert(index) {
+            return Node {
+                label: format!("{label} ↑"),
+                children: Vec::new(),
+            };
+        }
+        let children = self
+            .edges
+            .iter()
+            .filter(|(from, _, _)| *from == index)
+            .map(|(_, to, field)| self.node(*to, Some(field), seen))
+            .collect();
+        Node { label, children }
+    }
+
+    pub fn tree(&self) -> String {
+        if self.vertices.is_empty() {
+            return String::new();
+        }
+        self.node(0, None, &mut HashSet::new()).lines().join("\n")
+    }
+
+    pub fn dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut dot = String::from("digraph topology {\n    rankdir=LR;\n    node [shape=box];\n");
+        for v in &self.vertices {
+            let name = v.value["name"].as_str().filter(|n| !n.is_empty());
+            let label = format!("{}\n{}", v.res_type, name.unwrap_or(&v.id.to_string()));
+            dot.push_str(&format!(
+                "    {} [label={}];\n",
+                quote(&v.id.to_string()),
+                quote(&label)
+            ));
+        }
+        for (from, to, field) in &self.edges {
+            dot.push_str(&format!(
+                "    {} -> {} [label={}];\n",
+                quote(&self.vertices[*from].id.to_string()),
+                quote(&self.vertices[*to].id.to_string()),
+                quote(field)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+struct Topology<'a> {
+    api: Rest,
+    cfg: &'a Config,
+    graph: Graph,
+    index: HashMap<Uuid, usize>,
+}
+
+impl<'a> Topology<'a> {
+    fn new(cfg: &'a Config) -> Self {
+        let mut api = Rest::new(cfg);
+        api.set_bench(false);
+        Self {
+            api,
+            cfg,
+            graph: Graph::default(),
+            index: HashMap::new(),
+        }
+    }
+
+    async fn read(&mut self, res_type: &str, id: Uuid) -> Result<Value, Error> {
+        let uri = config::uri_of(&self.cfg.resource, res_type);
+        if uri.contains('<') {
+            return Err(anyhow!("nested URI"));
+        }
+        let body = ResourceBuilder::new()
+            .res_type(res_type)
+            .oper("READ")
+            .id(id)
+            .build()?;
+        Ok(self.api.post(&uri, body).await?.json().await?)
+    }
+
+    /// Index of resource in graph, which is read when it's added
+    async fn vertex(&mut self, res_type: &str, id: Uuid) -> usize {
+        if let Some(index) = self.index.get(&id) {
+            return *index;
+        }
+        let (value, error) = match self.read(res_type, id).await {
+            Ok(value) => (value, None),
+            Err(e) => {
+                let error = match e.downcast_ref::<HttpError>() {
+                    Some(http) => http.status.to_string(),
+                    None => e.to_string(),
+                };
+                (Value::Null, Some(error))
+            }
+        };
+        self.graph.vertices.push(Vertex {
+            res_type: res_type.to_string(),
+            id,
+            value,
+            error,
+        });
+        self.index.insert(id, self.graph.vertices.len() - 1);
+        self.graph.vertices.len() - 1
+    }
+
+    /// Follow references level by level from the root
+    async fn build(&mut self, res_type: &str, id: Uuid, depth: usize) {
+        let root = self.vertex(res_type, id).await;
+        let mut queue = VecDeque::from([(root, 0)]);
+        let mut expanded = HashSet::new();
+        while let Some((index, level)) = queue.pop_front() {
+            if level >= depth || !expanded.insert(index) {
+                continue;
+            }
+            for (field, res_type, id) in references(&self.graph.vertices[index].value) {
+                if id == self.graph.vertices[index].id {
+                    continue;
+                }
+                let to = self.vertex(res_type, id).await;
+                self.graph.edges.push((index, to, field));
+                queue.push_back((to, level + 1));
+            }
+        }
+    }
+}
+
+pub async fn handle(cfg: &Config, opts: TopologyOpts) -> Result<(), Error> {
+    // Both command and type are accepted, e.g. `net` and `network`
+    let res_type = cfg
+        .resource
+        .iter()
+        .find(|r| r.cmd == opts.res_type || r.resource == opts.res_type)
+        .map_or(opts.res_type.clone(), |r| r.resource.clone());
+    let mut topology = Topology::new(cfg);
+    let id = match Uuid::parse_str(&opts.name) {
+        Ok(id) => id,
+        Err(_) => {
+            let uri = config::uri_of(&cfg.resource, &res_type);
+            topology.api.name_to_id(&uri, &opts.name).await?
+        }
+    };
+    topology.build(&res_type, id, opts.depth).await;
+    if let Some(error) = &topology.graph.vertices[0].error {
+        return Err(anyhow!("Failed to read {} {}: {}", res_type, id, error));
+    }
+    match opts.format {
+        TopologyFormat::Tree => println!("{}", topology.graph.tree()),
+        TopologyFormat::Dot => print!("{}", topology.graph.dot()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_topology() {
+        let (p, n, s, r) = (
+            "6bd0768b-0beb-4b30-9916-a3c445fede1c",
+            "5c6c4a8e-2e5b-4c0c-9f4b-7b7f0a3c1d2e",
+            "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d",
+            "1b2c3d4e-5f6a-4b7c-8d9e-0f1a2b3c4d5e",
+        );
+        let port = json!({
+            "id": p, "name": "gw", "status": "ACTIVE", "network_id": n,
+            "fixed_ips": [{"subnet_id": s, "ip_address": "10.0.0.1"}],
+            "device_owner": "network:router_interface", "device_id": r,
+            "security_groups": [],
+        });
+        let mut refs = references(&port);
+        refs.sort();
+        let refs: Vec<_> = refs.iter().map(|(f, t, _)| (f.as_str(), *t)).collect();
+        assert_eq!(
+            refs,
+            [
+                ("device_id", "router"),
+                ("network_id", "network"),
+                ("subnet_id", "subnet")
+            ]
+        );
+        assert!(references(&json!({"device_id": r, "device_owner": "compute:nova"})).is_empty());
+
+        let vertex = |res_type: &str, id: &str, value: Value| Vertex {
+            res_type: res_type.to_string(),
+            id: Uuid::parse_str(id).unwrap(),
+            value,
+            error: None,
+        };
+        let graph = Graph {
+            vertices: vec![
+                vertex("port", p, port.clone()),
+                vertex("network", n, json!({"name": "n1", "subnets": [s]})),
+                vertex("subnet", s, json!({"name": "s1", "network_id": n})),
+            ],
+            edges: vec![
+                (0, 1, String::from("network_id")),
+                (0, 2, String::from("subnet_id")),
+                (1, 2, String::from("subnets")),
+                (2, 1, String::from("network_id")),
+            ],
+        };
+        let tree = graph.tree();
+        let lines: Vec<_> = tree.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], format!("port gw ({p}) [ACTIVE]"));
+        assert_eq!(lines[1], format!("├── network_id → network n1 ({n})"));
+        assert!(lines[3].ends_with(&format!("network_id → network n1 ({n}) ↑")));
+        assert!(lines[4].ends_with(&format!("subnet_id → subnet s1 ({s}) ↑")));
+        let dot = graph.dot();
+        assert!(dot.contains(&format!("\"{p}\" [label=\"port\ngw\"];")));
+        assert!(dot.contains(&format!("\"{p}\" -> \"{n}\" [label=\"network_id\"];")));
+    }
+}
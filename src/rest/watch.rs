@@ -0,0 +1,99 @@
+//! `--watch` of `show` and `list`, which polls resources and highlights what is changed
+//! since the previous poll, until `--until` expressions hold for all of them.
+use super::filter::{matches_all, Cond};
+use super::output::{items, note, print_output, print_table_with, quiet, OutputFormat};
+use super::path::lookup;
+use chrono::Local;
+use serde_json::Value;
+use std::{
+    io::{self, IsTerminal},
+    time::Duration,
+};
+
+/// The same resource in previous poll, by ID in a list
+fn same<'a>(previous: &'a Value, item: &Value) -> Option<&'a Value> {
+    match previous {
+        Value::Array(array) => array
+            .iter()
+            .find(|p| p.get("id").is_some() && p.get("id") == item.get("id")),
+        previous => Some(previous),
+    }
+}
+
+/// Whether value of path is changed, everything of a new resource is
+fn changed(previous: Option<&Value>, item: &Value, path: &str) -> bool {
+    match previous {
+        None => false,
+        Some(previous) => match same(previous, item) {
+            Some(old) => lookup(old, path) != lookup(item, path),
+            None => true,
+        },
+    }
+}
+
+/// Whether the condition holds, which needs at least one resource
+fn done(until: &[Cond], value: &Value) -> bool {
+    let items = items(value);
+    !until.is_empty() && !items.is_empty() && items.iter().all(|i| matches_all(until, i))
+}
+
+pub async fn watch(
+    interval: u64,
+    fmt: &OutputFormat,
+    fields: Option<Vec<String>>,
+    until: &[Cond],
+    mut fetch: impl AsyncFnMut() -> anyhow::Result<Value>,
+) -> anyhow::Result<()> {
+    // Redraw in place only for a table on terminal
+    let redraw = *fmt == OutputFormat::Table && io::stdout().is_terminal() && !quiet();
+    let mut previous: Option<Value> = None;
+    loop {
+        let value = fetch().await?;
+        if redraw {
+            print!("\x1b[2J\x1b[H");
+        }
+        note(format!(
+            "Every {}s: {}",
+            interval,
+            Local::now().format("%Y-%m-%d %H:%M:%S")
+        ));
+        match fmt {
+            OutputFormat::Table => {
+                let len = print_table_with(&value, fields.clone(), |item, path| {
+                    changed(previous.as_ref(), item, path)
+                });
+                note(format!("Total: {}", len));
+            }
+            fmt => print_output(&value, fmt, fields.clone()),
+        }
+        if done(until, &value) {
+            return Ok(());
+        }
+        previous = Some(value);
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::filter::where_parser;
+    use serde_json::json;
+
+    #[test]
+    fn test_changed() {
+        let previous = json!([{"id": "1", "status": "DOWN"}, {"id": "2", "status": "ACTIVE"}]);
+        let port = json!({"id": "1", "status": "ACTIVE"});
+        assert!(changed(Some(&previous), &port, "status"));
+        assert!(!changed(Some(&previous), &previous[1], "status"));
+        assert!(changed(Some(&previous), &json!({"id": "3"}), "id"));
+        assert!(!changed(None, &port, "status"));
+        assert!(!changed(Some(&port), &port, "status"));
+
+        let until = vec![where_parser("status=ACTIVE").unwrap()];
+        assert!(!done(&until, &previous));
+        assert!(done(&until, &port));
+        assert!(!done(&until, &json!([])));
+        assert!(!done(&[], &port));
+    }
+}
@@ -1 +1,4 @@
+pub mod cli;
+pub mod scenario;
+#[allow(clippy::module_inception)]
 pub mod stress;
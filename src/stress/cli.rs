@@ -0,0 +1,60 @@
+use super::scenario::Scenario;
+use super::stress::{run, Job, Kind};
+use crate::config::read_config;
+use crate::rest::rest::Rest;
+use clap::{ArgMatches, Args, Command, FromArgMatches};
+use std::{path::PathBuf, sync::Arc};
+
+#[derive(Args)]
+struct Opts {
+    /// Resource to be created / read / deleted
+    #[arg(value_enum, required_unless_present = "scenario")]
+    resource: Option<Kind>,
+
+    /// Scenario file (TOML or JSON5) describing a topology built by each worker
+    #[arg(short, long, conflicts_with = "resource")]
+    scenario: Option<PathBuf>,
+
+    /// Number of concurrent workers
+    #[arg(short, long, default_value_t = 1)]
+    worker: u16,
+
+    /// Number of resources (or topologies for scenario) created by each worker
+    #[arg(short, long, default_value_t = 10)]
+    number: u16,
+}
+
+pub fn build_cli(cmd: Command) -> Command {
+    let cli = Command::new("stress").about("Stress SDN API with concurrent requests");
+    let cli = Opts::augment_args(cli);
+    cmd.subcommand(cli)
+}
+
+pub async fn handle_cli(matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    if let Some(matches) = matches.subcommand_matches("stress") {
+        let cmd = Opts::from_arg_matches(matches)
+            .map_err(|err| err.exit())
+            .unwrap();
+        let job = match (cmd.resource, &cmd.scenario) {
+            (Some(kind), _) => Job::Resource(kind),
+            (None, Some(file)) => Job::Scenario(Arc::new(Scenario::read(file)?)),
+            (None, None) => unreachable!(),
+        };
+        let cfg = read_config()?;
+        let mut api = Rest::new(&cfg);
+        api.set_bench(false);
+        println!(
+            "** Stress {} on {} with {} worker(s) x {} ...",
+            match (&cmd.resource, &cmd.scenario) {
+                (Some(kind), _) => format!("{kind:?}"),
+                (_, file) => format!("{:?}", file.as_ref().unwrap()),
+            },
+            api.host,
+            cmd.worker,
+            cmd.number
+        );
+        run(api, &cfg.resource, cmd.worker, cmd.number, job).await?;
+    }
+
+    Ok(())
+}
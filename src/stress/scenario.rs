@@ -0,0 +1,258 @@
+//! Scenario describes a topology built by each stress worker, in TOML or JSON5:
+//!
+//! ```toml
+//! [[step]]
+//! name = "net"
+//! type = "network"
+//!
+//! [[step]]
+//! name = "subnet"
+//! type = "subnet"
+//! attr = { network_id = "{net}", cidr = "{cidr}", ip_version = 4 }
+//!
+//! [[step]]
+//! name = "sg"
+//! type = "security_group"
+//!
+//! [[step]]
+//! name = "port"
+//! type = "port"
+//! count = 5
+//! attr = { network_id = "{net}", security_groups = ["{sg}"] }
+//!
+//! [[step]]
+//! name = "router"
+//! type = "router"
+//!
+//! [[step]]
+//! name = "router-if"
+//! type = "router"
+//! oper = "ADDINTERFACE"
+//! undo = "DELINTERFACE"
+//! id = "{router}"
+//! attr = { subnet_id = "{subnet}" }
+//! ```
+//!
+//! `{step}` is replaced by the ID created by that step (the same index if it creates several),
+//! `{worker}`, `{index}` and `{cidr}` are also provided. Resources are deleted in reverse order.
+use anyhow::{anyhow, Error};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+pub const BUILTIN_VARS: [&str; 3] = ["worker", "index", "cidr"];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    #[serde(rename = "step")]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    /// Symbolic name to reference IDs created by this step
+    pub name: String,
+    #[serde(rename = "type")]
+    pub resource: String,
+    /// Number of resources created by this step
+    #[serde(default = "default_count")]
+    pub count: u16,
+    /// Operation sent to API, `CREATE` by default
+    #[serde(default = "default_oper")]
+    pub oper: String,
+    /// Resource ID for operations other than `CREATE`
+    pub id: Option<String>,
+    /// Operation to revert a non-`CREATE` step when tearing down
+    pub undo: Option<String>,
+    #[serde(default)]
+    pub attr: Map<String, Value>,
+}
+
+fn default_count() -> u16 {
+    1
+}
+
+fn default_oper() -> String {
+    String::from("CREATE")
+}
+
+impl Step {
+    pub fn is_create(&self) -> bool {
+        self.oper.eq_ignore_ascii_case("CREATE")
+    }
+
+    /// Whether `{var}` is referenced by attributes
+    pub fn uses(&self, var: &str) -> bool {
+        let mut refs = Vec::new();
+        self.attr.values().for_each(|v| value_placeholders(v, &mut refs));
+        refs.iter().any(|r| r == var)
+    }
+}
+
+impl Scenario {
+    pub fn read(file: &PathBuf) -> Result<Self, Error> {
+        let data = fs::read_to_string(file)?;
+        let scenario: Scenario = match file.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&data)?,
+            _ => json5::from_str(&data)
+                .map_err(|e| anyhow!("Failed to parse scenario file - {}", e))?,
+        };
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Resource types used by this scenario
+    pub fn res_types(&self) -> Vec<String> {
+        let mut types: Vec<_> = self.steps.iter().map(|s| s.resource.clone()).collect();
+        types.sort();
+        types.dedup();
+        types
+    }
+
+    /// Every reference must point to a step defined before it.
+    fn validate(&self) -> Result<(), Error> {
+        if self.steps.is_empty() {
+            return Err(anyhow!("No step found in scenario"));
+        }
+        let mut known: Vec<&str> = BUILTIN_VARS.to_vec();
+        for step in &self.steps {
+            if known.contains(&step.name.as_str()) {
+                return Err(anyhow!("Duplicated step name `{}`", step.name));
+            }
+            if !step.is_create() && step.id.is_none() {
+                return Err(anyhow!("Step `{}` needs `id` for {}", step.name, step.oper));
+            }
+            let mut refs = Vec::new();
+            step.id.iter().for_each(|id| placeholders(id, &mut refs));
+            step.attr
+                .values()
+                .for_each(|v| value_placeholders(v, &mut refs));
+            if let Some(r) = refs.iter().find(|r| !known.contains(&r.as_str())) {
+                return Err(anyhow!(
+                    "Step `{}` references unknown `{{{}}}`",
+                    step.name,
+                    r
+                ));
+            }
+            known.push(&step.name);
+        }
+        Ok(())
+    }
+}
+
+fn placeholders(s: &str, refs: &mut Vec<String>) {
+    let mut rest = s;
+    while let Some(start) = rest.find('{') {
+        match rest[start..].find('}') {
+            Some(end) => {
+                refs.push(rest[start + 1..start + end].to_string());
+                rest = &rest[start + end..];
+            }
+            None => break,
+        }
+    }
+}
+
+fn value_placeholders(v: &Value, refs: &mut Vec<String>) {
+    match v {
+        Value::String(s) => placeholders(s, refs),
+        Value::Array(a) => a.iter().for_each(|v| value_placeholders(v, refs)),
+        Value::Object(o) => o.values().for_each(|v| value_placeholders(v, refs)),
+        _ => (),
+    }
+}
+
+/// Replace `{var}` in all strings of a value
+pub fn render(v: &Value, vars: &HashMap<String, String>) -> Value {
+    match v {
+        Value::String(s) => Value::String(render_str(s, vars)),
+        Value::Array(a) => Value::Array(a.iter().map(|v| render(v, vars)).collect()),
+        Value::Object(o) => Value::Object(
+            o.iter()
+                .map(|(k, v)| (k.clone(), render(v, vars)))
+                .collect(),
+        ),
+        _ => v.clone(),
+    }
+}
+
+pub fn render_str(s: &str, vars: &HashMap<String, String>) -> String {
+    vars.iter()
+        .fold(s.to_string(), |s, (k, v)| s.replace(&format!("{{{k}}}"), v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_scenario() {
+        let toml = r#"
+            [[step]]
+            name = "net"
+            type = "network"
+
+            [[step]]
+            name = "port"
+            type = "port"
+            count = 2
+            attr = { network_id = "{net}", name = "p-{worker}-{index}", security_groups = ["{sg}"] }
+        "#;
+        let scenario: Scenario = toml::from_str(toml).unwrap();
+        assert!(scenario.validate().is_err());
+
+        let vars = HashMap::from([
+            (String::from("net"), String::from("n1")),
+            (String::from("worker"), String::from("0")),
+            (String::from("index"), String::from("3")),
+            (String::from("sg"), String::from("s1")),
+        ]);
+        let attr = render(&Value::Object(scenario.steps[1].attr.clone()), &vars);
+        assert_eq!(
+            attr,
+            json!({"network_id": "n1", "name": "p-0-3", "security_groups": ["s1"]})
+        );
+    }
+
+    #[test]
+    fn test_doc_example() {
+        // Same as the example in module doc
+        let toml = r#"
+            [[step]]
+            name = "net"
+            type = "network"
+
+            [[step]]
+            name = "subnet"
+            type = "subnet"
+            attr = { network_id = "{net}", cidr = "{cidr}", ip_version = 4 }
+
+            [[step]]
+            name = "sg"
+            type = "security_group"
+
+            [[step]]
+            name = "port"
+            type = "port"
+            count = 5
+            attr = { network_id = "{net}", security_groups = ["{sg}"] }
+
+            [[step]]
+            name = "router"
+            type = "router"
+
+            [[step]]
+            name = "router-if"
+            type = "router"
+            oper = "ADDINTERFACE"
+            undo = "DELINTERFACE"
+            id = "{router}"
+            attr = { subnet_id = "{subnet}" }
+        "#;
+        let scenario: Scenario = toml::from_str(toml).unwrap();
+        scenario.validate().unwrap();
+        assert!(scenario.steps[1].uses("cidr"));
+        assert!(!scenario.steps[3].uses("cidr"));
+    }
+}
@@ -1,9 +1,21 @@
-use tokio;
-use reqwest::{self, Client, Response, RequestBuilder, Method}; 
+use super::scenario::{render, render_str, Scenario};
+use crate::{
+    config, rest::ledger::Ledger, rest::metrics, rest::resource::ResourceBuilder, rest::rest::Rest,
+};
+use anyhow::anyhow;
+use clap::ValueEnum;
+use log::{info, warn};
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, net::Ipv4Addr, sync::Arc};
+use uuid::Uuid;
 
-let ip [u8: 4] = [ 192, 18, 0, 0 ]
+/// Stress allocates /24 subnets in 198.18.0.0/15 reserved for benchmarking (RFC 2544)
+const BASE_IP: Ipv4Addr = Ipv4Addr::new(198, 18, 0, 0);
+/// Number of /24 subnets in the base range
+const SUBNETS: u32 = 512;
 
-enum {
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Kind {
     Network,
     ExternalNetwork,
     Subnet,
@@ -14,4 +26,402 @@ enum {
     Tag,
     Lb,
 }
-pub fn run(worker: u16, number: u16, resource: )
+
+impl Kind {
+    pub fn res_type(&self) -> &'static str {
+        match self {
+            Kind::Network | Kind::ExternalNetwork => "network",
+            Kind::Subnet => "subnet",
+            Kind::Port => "port",
+            Kind::Router => "router",
+            Kind::Sg => "security_group",
+            Kind::Fw => "firewall_group",
+            Kind::Tag => "tag",
+            Kind::Lb => "loadbalancer",
+        }
+    }
+
+    /// Resources which can not be created without a network and subnet
+    fn need_subnet(&self) -> bool {
+        matches!(self, Kind::Subnet | Kind::Port | Kind::Lb)
+    }
+
+    fn attr(&self, name: &str, parent: &Parent, seq: u32) -> Map<String, Value> {
+        let mut attr = Map::new();
+        attr.insert(String::from("name"), json!(name));
+        match self {
+            Kind::ExternalNetwork => {
+                attr.insert(String::from("router:external"), json!(true));
+            }
+            Kind::Subnet => {
+                attr.insert(String::from("network_id"), json!(parent.network));
+                attr.insert(String::from("ip_version"), json!(4));
+                attr.insert(String::from("cidr"), json!(cidr(seq)));
+            }
+            Kind::Port => {
+                attr.insert(String::from("network_id"), json!(parent.network));
+            }
+            Kind::Lb => {
+                attr.insert(String::from("vip_subnet_id"), json!(parent.subnet));
+            }
+            _ => (),
+        }
+        attr
+    }
+}
+
+/// Resources created by a worker before stress and deleted after it
+#[derive(Default)]
+struct Parent {
+    network: Option<Uuid>,
+    subnet: Option<Uuid>,
+}
+
+/// What each worker does in a stress run
+#[derive(Clone)]
+pub enum Job {
+    /// Create, read and delete one kind of resource
+    Resource(Kind),
+    /// Build and tear down a whole topology
+    Scenario(Arc<Scenario>),
+}
+
+impl Job {
+    fn res_types(&self) -> Vec<String> {
+        match self {
+            Job::Resource(kind) => ["network", "subnet", kind.res_type()]
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            Job::Scenario(scenario) => scenario.res_types(),
+        }
+    }
+}
+
+/// Operation to revert a scenario step
+struct Undo {
+    res_type: String,
+    oper: String,
+    id: Uuid,
+    attr: Option<Map<String, Value>>,
+}
+
+fn cidr(seq: u32) -> String {
+    format!("{}/24", Ipv4Addr::from(u32::from(BASE_IP) + (seq << 8)))
+}
+
+/// Subnets of the base range reserved for a worker, so that workers never overlap
+#[derive(Debug)]
+struct Block {
+    start: u32,
+    end: u32,
+    next: u32,
+}
+
+impl Block {
+    /// Same sized blocks for all workers
+    fn new(id: u16, workers: u16) -> anyhow::Result<Self> {
+        let size = SUBNETS / u32::from(workers.max(1));
+        if size == 0 {
+            return Err(anyhow!(
+                "At most {} workers, each of them needs a subnet in {}/15",
+                SUBNETS,
+                BASE_IP
+            ));
+        }
+        let start = u32::from(id) * size;
+        Ok(Self {
+            start,
+            end: start + size,
+            next: start,
+        })
+    }
+
+    /// Next subnet, None if all of the block are in use
+    fn take(&mut self) -> Option<u32> {
+        (self.next < self.end).then(|| {
+            self.next += 1;
+            self.next - 1
+        })
+    }
+}
+
+struct Worker {
+    id: u16,
+    api: Rest,
+    uris: Arc<HashMap<String, String>>,
+    ledger: Ledger,
+    /// Subnets to allocate CIDR from
+    block: Block,
+}
+
+impl Worker {
+    /// Send one request. Return response JSON if succeed.
+    async fn call(
+        &mut self,
+        res_type: &str,
+        oper: &str,
+        id: Option<Uuid>,
+        attr: Option<Map<String, Value>>,
+    ) -> Option<Value> {
+        let mut builder = ResourceBuilder::new();
+        builder.res_type(res_type).oper(oper);
+        if let Some(id) = id {
+            builder.id(id);
+        }
+        if let Some(attr) = attr {
+            builder.resource(attr);
+        }
+        let body = builder.build().ok()?;
+        let uri = &self.uris[res_type];
+        let result = match self.api.post(uri, body).await {
+            // DELETE and some operations return nothing
+            Ok(response) => match response.text().await {
+                Ok(text) if text.is_empty() => Ok(Value::Null),
+                Ok(text) => serde_json::from_str::<Value>(&text).map_err(|e| anyhow!(e)),
+                Err(e) => Err(anyhow!(e)),
+            },
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(v) => {
+                match (oper, id) {
+                    ("CREATE", _) => self.ledger.created_value(res_type, uri, &v),
+                    ("DELETE", Some(id)) => self.ledger.deleted(res_type, uri, id),
+                    _ => (),
+                }
+                Some(v)
+            }
+            Err(e) => {
+                info!("worker {}: {} {} failed - {}", self.id, oper, res_type, e);
+                None
+            }
+        }
+    }
+
+    async fn create(&mut self, res_type: &str, attr: Map<String, Value>) -> Option<Uuid> {
+        let v = self.call(res_type, "CREATE", None, Some(attr)).await?;
+        Uuid::parse_str(v["id"].as_str()?).ok()
+    }
+
+    async fn delete(&mut self, res_type: &str, id: Option<Uuid>) {
+        if id.is_some() {
+            self.call(res_type, "DELETE", id, None).await;
+        }
+    }
+
+    async fn run(mut self, job: Job, number: u16) {
+        match job {
+            Job::Resource(kind) => self.run_resource(kind, number).await,
+            Job::Scenario(scenario) => {
+                for i in 0..number {
+                    self.run_scenario(&scenario, i).await;
+                }
+            }
+        }
+    }
+
+    async fn run_resource(&mut self, kind: Kind, number: u16) {
+        let mut parent = Parent::default();
+        if kind.need_subnet() {
+            let name = format!("sdncli-stress-{}", self.id);
+            // Subnets stressed take the block themselves, others need one as parent
+            let seq = match kind {
+                Kind::Subnet => None,
+                _ => match self.block.take() {
+                    Some(seq) => Some(seq),
+                    None => {
+                        info!("worker {}: no subnet left in {:?}", self.id, self.block);
+                        return;
+                    }
+                },
+            };
+            parent.network = self
+                .create("network", Kind::Network.attr(&name, &parent, 0))
+                .await;
+            if let Some(seq) = seq {
+                let attr = Kind::Subnet.attr(&name, &parent, seq);
+                parent.subnet = self.create("subnet", attr).await;
+            }
+            if parent.network.is_none() {
+                return;
+            }
+        }
+
+        for i in 0..number {
+            let name = format!("sdncli-stress-{}-{}", self.id, i);
+            let res_type = kind.res_type();
+            // Each subnet is deleted before the next, so the same CIDR is taken again.
+            // Other kinds don't use it.
+            let next = self.block.next;
+            let seq = match kind {
+                Kind::Subnet => match self.block.take() {
+                    Some(seq) => seq,
+                    None => {
+                        info!("worker {}: no subnet left in {:?}", self.id, self.block);
+                        break;
+                    }
+                },
+                _ => 0,
+            };
+            if let Some(id) = self.create(res_type, kind.attr(&name, &parent, seq)).await {
+                self.call(res_type, "READ", Some(id), None).await;
+                self.delete(res_type, Some(id)).await;
+            }
+            self.block.next = next;
+        }
+
+        self.delete("subnet", parent.subnet).await;
+        self.delete("network", parent.network).await;
+    }
+
+    /// Build the topology step by step, then tear it down in reverse order.
+    /// Stop building at the first failure since later steps may depend on it.
+    async fn run_scenario(&mut self, scenario: &Scenario, index: u16) {
+        let mut created: HashMap<&str, Vec<Uuid>> = HashMap::new();
+        let mut undo: Vec<Undo> = Vec::new();
+        // Subnets of the last run are deleted already
+        self.block.next = self.block.start;
+
+        'build: for step in &scenario.steps {
+            for i in 0..step.count {
+                let mut vars: HashMap<String, String> = created
+                    .iter()
+                    .map(|(k, ids)| {
+                        let id = ids.get(i as usize).unwrap_or(&ids[0]);
+                        (k.to_string(), id.to_string())
+                    })
+                    .collect();
+                vars.insert(String::from("worker"), self.id.to_string());
+                vars.insert(String::from("index"), index.to_string());
+                if step.uses("cidr") {
+                    let Some(seq) = self.block.take() else {
+                        info!("worker {}: no subnet left in {:?}", self.id, self.block);
+                        break 'build;
+                    };
+                    vars.insert(String::from("cidr"), cidr(seq));
+                }
+
+                let attr = match render(&Value::Object(step.attr.clone()), &vars) {
+                    Value::Object(attr) => attr,
+                    _ => unreachable!(),
+                };
+                if step.is_create() {
+                    let mut attr = attr;
+                    attr.entry("name").or_insert(json!(format!(
+                        "sdncli-stress-{}-{}-{}-{}",
+                        self.id, index, step.name, i
+                    )));
+                    let Some(id) = self.create(&step.resource, attr).await else {
+                        break 'build;
+                    };
+                    created.entry(&step.name).or_default().push(id);
+                    undo.push(Undo {
+                        res_type: step.resource.clone(),
+                        oper: String::from("DELETE"),
+                        id,
+                        attr: None,
+                    });
+                } else {
+                    let id = render_str(step.id.as_deref().unwrap_or_default(), &vars);
+                    let Ok(id) = Uuid::parse_str(&id) else {
+                        break 'build;
+                    };
+                    let oper = step.oper.to_uppercase();
+                    if self
+                        .call(&step.resource, &oper, Some(id), Some(attr.clone()))
+                        .await
+                        .is_none()
+                    {
+                        break 'build;
+                    }
+                    if let Some(oper) = &step.undo {
+                        undo.push(Undo {
+                            res_type: step.resource.clone(),
+                            oper: oper.to_uppercase(),
+                            id,
+                            attr: Some(attr),
+                        });
+                    }
+                }
+            }
+        }
+
+        while let Some(u) = undo.pop() {
+            self.call(&u.res_type, &u.oper, Some(u.id), u.attr).await;
+        }
+    }
+}
+
+pub async fn run(
+    mut api: Rest,
+    resources: &[config::Resource],
+    worker: u16,
+    number: u16,
+    job: Job,
+) -> anyhow::Result<()> {
+    // Share one token among all workers
+    api.authenticate().await?;
+    let uris: Arc<HashMap<_, _>> = Arc::new(
+        job.res_types()
+            .into_iter()
+            .map(|t| {
+                let uri = config::uri_of(resources, &t);
+                (t, uri)
+            })
+            .collect(),
+    );
+
+    let ledger = Ledger::new()?;
+    let mut workers = Vec::new();
+    for id in 0..worker {
+        workers.push(Worker {
+            id,
+            api: api.clone(),
+            uris: uris.clone(),
+            ledger: ledger.clone(),
+            block: Block::new(id, worker)?,
+        });
+    }
+    metrics::start();
+    let handles: Vec<_> = workers
+        .into_iter()
+        .map(|w| tokio::spawn(w.run(job.clone(), number)))
+        .collect();
+
+    for handle in handles {
+        handle.await?;
+    }
+    // Two lines are recorded for each resource, most of which are deleted already
+    if let Err(e) = ledger.tidy() {
+        warn!("Failed to compact ledger - {}", e);
+    }
+    metrics::report();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr() {
+        assert_eq!(cidr(0), "198.18.0.0/24");
+        assert_eq!(cidr(1), "198.18.1.0/24");
+        assert_eq!(cidr(256), "198.19.0.0/24");
+        assert_eq!(cidr(SUBNETS - 1), "198.19.255.0/24");
+    }
+
+    #[test]
+    fn test_block() {
+        let mut last = Block::new(3, 4).unwrap();
+        assert_eq!((last.start, last.end), (384, 512));
+        let mut block = Block::new(0, 512).unwrap();
+        assert_eq!(block.take(), Some(0));
+        assert_eq!(block.take(), None);
+        last.next = 511;
+        assert_eq!(last.take(), Some(511));
+        assert_eq!(last.take(), None);
+        assert!(Block::new(0, 513).is_err());
+    }
+}